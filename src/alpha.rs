@@ -0,0 +1,139 @@
+use image::{DynamicImage, Rgba, RgbaImage};
+
+use crate::{
+    color_palette::ColorMapElement,
+    dithering::{DitherOptions, DitheringType},
+    pixel_util::RGB,
+};
+
+// what happens to transparency, without any the alpha channel is ignored and the output is opaque
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AlphaMode {
+    // pixels less opaque than the threshold are transparent, the others opaque
+    Threshold(f64),
+    // the alpha channel is dithered to transparent and opaque with the dithering type of the image
+    Dither,
+    // composited over a color before anything else, the lightest color of the color map when not
+    // given, the output is opaque
+    Background(Option<RGB>),
+}
+
+impl AlphaMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Threshold(_) => "threshold",
+            Self::Dither => "dither",
+            Self::Background(_) => "background",
+        }
+    }
+}
+
+pub const TRANSPARENT: RGB = RGB { r: 0.0, g: 0.0, b: 0.0, a: 0.0 };
+
+// the image over a plain color, fully opaque
+pub fn composite(image: &DynamicImage, color: RGB) -> DynamicImage {
+    let background = [color.r, color.g, color.b].map(|channel| channel.clamp(0.0, 1.0) * 255.0);
+    let mut pixels = image.to_rgba8();
+    for pixel in pixels.pixels_mut() {
+        let alpha = pixel.0[3] as f64 / 255.0;
+        for (channel, background) in pixel.0[..3].iter_mut().zip(background) {
+            *channel = (*channel as f64 * alpha + background * (1.0 - alpha)).round() as u8;
+        }
+        pixel.0[3] = 255;
+    }
+    DynamicImage::ImageRgba8(pixels)
+}
+
+// which pixels stay opaque, none is decided when compositing
+pub fn opaque(
+    alpha: &[f64],
+    width: u32,
+    height: u32,
+    mode: &AlphaMode,
+    dithering_type: &DitheringType,
+    options: &DitherOptions,
+) -> Option<Vec<bool>> {
+    match mode {
+        AlphaMode::Threshold(threshold) => Some(alpha.iter().map(|a| *a >= *threshold).collect()),
+        AlphaMode::Dither => {
+            let mut data = alpha
+                .iter()
+                .map(|a| RGB { r: *a, g: *a, b: *a, a: 1.0 })
+                .collect::<Vec<RGB>>();
+            let color_map = [
+                ColorMapElement { color: TRANSPARENT, scale: 1.0, offset: 0.0 },
+                ColorMapElement { color: RGB { r: 1.0, g: 1.0, b: 1.0, a: 1.0 }, scale: 1.0, offset: 0.0 },
+            ];
+            dithering_type.dither(&mut data, width, height, &color_map, options);
+            // only the partly transparent pixels are dithered, the thresholds can reach 0.0 and 1.0
+            Some(
+                alpha
+                    .iter()
+                    .zip(data.iter())
+                    .map(|(a, pixel)| *a >= 1.0 || (*a > 0.0 && pixel.a > 0.0))
+                    .collect(),
+            )
+        }
+        AlphaMode::Background(_) => None,
+    }
+}
+
+// gives transparent pixels a flat grey, so whatever color they hid doesn't spread into their
+// opaque neighbours through error diffusion
+pub fn clear(image: &DynamicImage, opaque: &[bool], grey: f64) -> DynamicImage {
+    let value = (grey.clamp(0.0, 1.0) * 255.0).round() as u8;
+    let mut pixels: RgbaImage = image.to_rgba8();
+    for (pixel, opaque) in pixels.pixels_mut().zip(opaque) {
+        if !opaque {
+            *pixel = Rgba([value, value, value, pixel.0[3]]);
+        }
+    }
+    DynamicImage::ImageRgba8(pixels)
+}
+
+// makes the transparent pixels of the output fully transparent
+pub fn cut(data: &mut [RGB], opaque: &[bool]) {
+    for (pixel, opaque) in data.iter_mut().zip(opaque) {
+        if !opaque {
+            *pixel = TRANSPARENT;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_composite() {
+        let image = RgbaImage::from_fn(2, 1, |x, _| if x == 0 { Rgba([0, 0, 0, 0]) } else { Rgba([0, 0, 0, 128]) });
+        let composited = composite(&image.into(), RGB::from_u8(255, 255, 255, 255)).to_rgba8();
+        assert_eq!(composited.get_pixel(0, 0).0, [255, 255, 255, 255]);
+        assert_eq!(composited.get_pixel(1, 0).0, [127, 127, 127, 255]);
+    }
+
+    #[test]
+    fn test_opaque() {
+        let (width, height) = (16, 16);
+        let alpha = (0..width * height)
+            .map(|index| match index {
+                0..128 => 0.3,
+                128..192 => 0.0,
+                _ => 1.0,
+            })
+            .collect::<Vec<f64>>();
+        let options = DitherOptions::default();
+
+        let thresholded = opaque(&alpha, width, height, &AlphaMode::Threshold(0.5), &DitheringType::Bayer1, &options).unwrap();
+        assert_eq!(thresholded.iter().filter(|o| **o).count(), 64);
+
+        // about 30% of the partly transparent pixels are kept
+        let dithered = opaque(&alpha, width, height, &AlphaMode::Dither, &DitheringType::Bayer1, &options).unwrap();
+        let kept = dithered[..128].iter().filter(|o| **o).count();
+        assert!((30..=50).contains(&kept), "{} kept", kept);
+        assert!(dithered[128..192].iter().all(|o| !*o));
+        assert!(dithered[192..].iter().all(|o| *o));
+
+        assert!(opaque(&alpha, width, height, &AlphaMode::Background(None), &DitheringType::Bayer1, &options).is_none());
+    }
+}
@@ -1,4 +1,4 @@
-use crate::pixel_util::RGB;
+use crate::{error::DitherError, pixel_util::RGB};
 
 #[derive(Debug, Clone, Copy)]
 pub struct ColorMapElement {
@@ -29,3 +29,33 @@ pub const DEFAULT_COLOR_MAP: [ColorMapElement; 2] = [
         offset: 0.0,
     },
 ];
+
+// builds an evenly spaced color map out of a comma separated list of hex colors,
+// e.g. "000000,ff0000,ffffff", used for quick palettes on the command line
+pub fn color_map_from_hex_list(list: &str) -> Result<Vec<ColorMapElement>, DitherError> {
+    let colors = list
+        .split(',')
+        .filter(|s| !s.trim().is_empty())
+        .map(|s| RGB::from_hex(s.to_string()))
+        .collect::<Result<Vec<RGB>, DitherError>>()?;
+
+    color_map_from_colors(&colors)
+}
+
+// an evenly spaced color map, from the darkest level to the lightest in the given order
+pub fn color_map_from_colors(colors: &[RGB]) -> Result<Vec<ColorMapElement>, DitherError> {
+    if colors.len() <= 1 {
+        return Err(DitherError::Palette("a color map needs 2 or more colors".to_string()));
+    }
+
+    let steps = (colors.len() - 1) as f64;
+    Ok(colors
+        .iter()
+        .enumerate()
+        .map(|(index, color)| ColorMapElement {
+            color: *color,
+            scale: 1.0 / steps,
+            offset: index as f64 / steps,
+        })
+        .collect())
+}
@@ -0,0 +1,143 @@
+use image::{DynamicImage, Rgba, RgbaImage};
+
+use crate::{
+    color_palette::ColorMapElement, config::ProcessConfig, dithering::DitheringType,
+    error::DitherError, font, run,
+};
+
+const BACKGROUND: Rgba<u8> = Rgba([255, 255, 255, 255]);
+const TEXT_COLOR: Rgba<u8> = Rgba([0, 0, 0, 255]);
+const PADDING: u32 = 8;
+
+// one cell of the contact sheet
+#[derive(Debug, Clone)]
+pub struct Variant {
+    pub label: String,
+    pub config: ProcessConfig,
+}
+
+// the values to compare, an empty list means "use the value from the base config"
+#[derive(Debug, Clone, Default)]
+pub struct CompareOptions {
+    pub dithering_types: Vec<DitheringType>,
+    pub brigthness_deltas: Vec<i32>,
+    pub constrast_deltas: Vec<f32>,
+    pub color_maps: Vec<Vec<ColorMapElement>>,
+}
+
+// every combination of the compared values, applied on top of the base config
+pub fn variants(base: &ProcessConfig, options: &CompareOptions) -> Vec<Variant> {
+    let dithering_types = or_base(&options.dithering_types, base.dithering_type.clone());
+    let brigthness_deltas = or_base(&options.brigthness_deltas, base.brigthness_delta);
+    let constrast_deltas = or_base(&options.constrast_deltas, base.constrast_delta);
+    let color_maps = or_base(&options.color_maps, base.color_map.clone());
+
+    let mut variants: Vec<Variant> = Vec::new();
+    for color_map in &color_maps {
+        for brigthness_delta in &brigthness_deltas {
+            for constrast_delta in &constrast_deltas {
+                for dithering_type in &dithering_types {
+                    let mut config = base.clone();
+                    config.dithering_type = dithering_type.clone();
+                    config.brigthness_delta = *brigthness_delta;
+                    config.constrast_delta = *constrast_delta;
+                    config.color_map = color_map.clone();
+
+                    variants.push(Variant {
+                        label: label(&config),
+                        config,
+                    });
+                }
+            }
+        }
+    }
+
+    variants
+}
+
+fn or_base<T: Clone>(values: &[T], base: T) -> Vec<T> {
+    if values.is_empty() {
+        vec![base]
+    } else {
+        values.to_vec()
+    }
+}
+
+pub fn label(config: &ProcessConfig) -> String {
+    let palette = config
+        .color_map
+        .iter()
+        .map(|element| element.color.to_hex())
+        .collect::<Vec<String>>()
+        .join(" ");
+
+    let dithering = match &config.dithering_type {
+        DitheringType::Expression(expr) => format!("expr {}", expr.source()),
+        DitheringType::CustomDotDiffusion(class_matrix) => format!(
+            "dot_diffusion {}x{}",
+            class_matrix.width(),
+            class_matrix.height()
+        ),
+        dtype => dtype.name().to_string(),
+    };
+
+    format!(
+        "{}\nb={} c={}\n{}",
+        dithering,
+        config.brigthness_delta,
+        config.constrast_delta,
+        palette
+    )
+}
+
+// renders every variant of the image and lays them out in a grid, with the label under each tile
+pub fn contact_sheet(
+    image: &DynamicImage,
+    variants: &[Variant],
+    columns: u32,
+) -> Result<DynamicImage, DitherError> {
+    if variants.is_empty() {
+        return Err(DitherError::config("variants", "nothing to compare"));
+    }
+
+    let tiles = variants
+        .iter()
+        .map(|variant| run(variant.config.clone(), image.clone()))
+        .collect::<Result<Vec<DynamicImage>, DitherError>>()?;
+
+    let columns = columns.clamp(1, tiles.len() as u32);
+    let rows = (tiles.len() as u32).div_ceil(columns);
+    let tile_width = tiles.iter().map(|tile| tile.width()).max().unwrap();
+    let tile_height = tiles.iter().map(|tile| tile.height()).max().unwrap();
+
+    let scale = (tile_width / 200).clamp(1, 4);
+    let labels = variants
+        .iter()
+        .map(|variant| font::wrap_text(&variant.label, scale, tile_width))
+        .collect::<Vec<Vec<String>>>();
+    let label_lines = labels.iter().map(|lines| lines.len() as u32).max().unwrap();
+    let label_height = label_lines * font::line_height(scale);
+
+    let cell_width = tile_width + PADDING;
+    let cell_height = tile_height + PADDING + label_height + PADDING;
+    let mut sheet = RgbaImage::from_pixel(
+        columns * cell_width + PADDING,
+        rows * cell_height + PADDING,
+        BACKGROUND,
+    );
+
+    for (index, (tile, lines)) in tiles.iter().zip(labels.iter()).enumerate() {
+        let x = PADDING + (index as u32 % columns) * cell_width;
+        let y = PADDING + (index as u32 / columns) * cell_height;
+
+        image::imageops::overlay(&mut sheet, &tile.to_rgba8(), x as i64, y as i64);
+
+        let mut text_y = y + tile_height + PADDING;
+        for line in lines {
+            font::draw_text(&mut sheet, x, text_y, line, scale, TEXT_COLOR);
+            text_y += font::line_height(scale);
+        }
+    }
+
+    Ok(DynamicImage::ImageRgba8(sheet))
+}
@@ -1,141 +1,598 @@
 use std::{
-    error::Error,
-    fmt::Display,
     fs::File,
     io::{Read, Write},
 };
 
+use image::ImageReader;
 use json::{object, JsonValue};
 
 use crate::{
+    alpha::AlphaMode,
     color_palette::{ColorMapElement, DEFAULT_COLOR_MAP},
-    dithering::DitheringType,
+    dithering::{
+        dbs::DbsOptions, dot_diffusion::ClassMatrix, expression::ThresholdExpr, importance::Importance,
+        mask::Mask,
+        noise::NoiseDistribution, DitherOptions, DitheringType,
+    },
+    edges::{EdgeDetector, EdgeOptions},
+    error::DitherError,
+    filters::{AutoTone, Filter},
     pixel_util::RGB,
+    resize::{Crop, FitMode, OutputScale, Pad, ResizeFilter},
+    spot::SpotColor,
+    stipple::StippleOptions,
 };
 
-#[derive(Debug)]
+// images, canvases and outputs larger than this are refused, their buffers couldn't be allocated
+pub const MAX_PIXELS: u64 = 1 << 26;
+
+#[derive(Debug, Clone)]
 pub struct ProcessConfig {
+    // run in order on the resized image, before brigthness_delta and constrast_delta
+    pub filters: Vec<Filter>,
+    // auto levels and clahe, before the filters
+    pub auto_tone: Option<AutoTone>,
     pub brigthness_delta: i32,
     pub constrast_delta: f32,
     pub dithering_type: DitheringType,
     pub color_map: Vec<ColorMapElement>,
     pub processing_width: u32,
     pub processing_height: u32,
-    pub output_scale: u32,
+    // how the image is brought to the processing size
+    pub resize_filter: ResizeFilter,
+    pub fit: FitMode,
+    // part of the original image kept, before resizing
+    pub crop: Option<Crop>,
+    // output pixels per processing pixel, can differ between x and y
+    pub output_scale: OutputScale,
+    // exact canvas the dithered image is centered on, before output_scale
+    pub pad: Option<Pad>,
+    // random dithering is only reproducible with a seed
+    pub seed: Option<u64>,
+    pub noise: NoiseDistribution,
+    pub hashed_noise: bool,
+    // the output tiles seamlessly, for textures
+    pub tileable: bool,
+    // refines the dithered image with a direct binary search
+    pub dbs: Option<DbsOptions>,
+    // draws dots instead of dithering
+    pub stipple: Option<StippleOptions>,
+    // outlines drawn over the dithered image
+    pub edges: Option<EdgeOptions>,
+    // transparency of the input kept, dithered or composited
+    pub alpha: Option<AlphaMode>,
+    // hues kept in color, the first matching one wins
+    pub spot_colors: Vec<SpotColor>,
+    // other settings for the white parts of a mask image
+    pub mask: Option<Mask>,
+    // coarser and flatter dithering where a grey map is dark
+    pub importance: Option<Importance>,
 }
 
 impl ProcessConfig {
-    fn to_config(json_string: String) -> Result<ProcessConfig, Box<dyn std::error::Error>> {
+    fn to_config(json_string: String) -> Result<ProcessConfig, DitherError> {
         let json = json::parse(json_string.as_str())?;
 
+        let mut filters: Vec<Filter> = Vec::new();
+        for (index, filter) in json["filters"].members().enumerate() {
+            match parse_filter(filter) {
+                Some(val) => filters.push(val),
+                None => return Err(DitherError::config(&format!("filters.{}", index), "couldn't parse")),
+            }
+        }
+
+        let auto_tone = match (json["auto_tone"].is_null(), json["auto_tone"].as_bool()) {
+            (true, _) | (_, Some(false)) => None,
+            (_, Some(true)) => Some(AutoTone::default()),
+            (_, None) if json["auto_tone"].is_object() => {
+                let default = AutoTone::default();
+                Some(AutoTone {
+                    clip: json["auto_tone"]["clip"].as_f64().unwrap_or(default.clip),
+                    tiles: json["auto_tone"]["tiles"].as_u32().unwrap_or(default.tiles),
+                    clip_limit: json["auto_tone"]["clip_limit"].as_f64().unwrap_or(default.clip_limit),
+                })
+            }
+            (_, None) => return Err(DitherError::config("auto_tone", "couldn't parse")),
+        };
+
         let brigthness_delta = match json["brigthness_delta"].as_i32() {
             Some(val) => val,
-            None => return ConfigError::get("Couldn't parse brigthness_delta"),
+            None => return Err(DitherError::config("brigthness_delta", "couldn't parse")),
         };
         let constrast_delta: f32 = match json["constrast_delta"].as_f32() {
             Some(val) => val,
-            None => return ConfigError::get("Couldn't parse constrast_delta"),
+            None => return Err(DitherError::config("constrast_delta", "couldn't parse")),
         };
         let processing_width: u32 = match json["processing_width"].as_u32() {
             Some(val) => val,
-            None => return ConfigError::get("Couldn't parse processing_width"),
+            None => return Err(DitherError::config("processing_width", "couldn't parse")),
         };
         let processing_height: u32 = match json["processing_height"].as_u32() {
             Some(val) => val,
-            None => return ConfigError::get("Couldn't parse processing_height"),
+            None => return Err(DitherError::config("processing_height", "couldn't parse")),
         };
-        let output_scale: u32 = match json["output_scale"].as_u32() {
-            Some(val) => val,
-            None => return ConfigError::get("Couldn't parse output_scale"),
-        };
-
-        let dithering_type: DitheringType = match json["dithering_type"].as_str() {
-            Some(s) => match s {
-                "rand" => DitheringType::Rand,
-                "bayer_0" => DitheringType::Bayer0,
-                "bayer_1" => DitheringType::Bayer1,
-                "bayer_2" => DitheringType::Bayer2,
-                "bayer_3" => DitheringType::Bayer3,
-                "blue_noise" => DitheringType::BlueNoise,
-                "atkinson" => DitheringType::Atkinson,
-                "jarvis" => DitheringType::JarvisJudiceNinke,
-                "floyd" => DitheringType::FloydSteinberg,
-                _ => return ConfigError::get("Not recognized dithering_type"),
-            },
-            None => return ConfigError::get("Couldn't parse dithering_type"),
-        };
-
-        let color_map = if json["color_map"].is_null() {
-            DEFAULT_COLOR_MAP.to_vec()
-        } else if json["color_map"].len() <= 1 {
-            return ConfigError::get("color_map should be an array of 2 or more colors objects");
+        let resize_filter = if json["resize_filter"].is_null() {
+            ResizeFilter::default()
         } else {
-            let mut index = 0;
-            let mut color_map: Vec<ColorMapElement> = Vec::new();
-            while index < json["color_map"].len() {
-                let color = match json["color_map"][index]["color"].as_str() {
-                    Some(val) => val.to_string(),
-                    None => match json["color_map"][index].as_str() {
-                        Some(val) => val.to_string(),
-                        None => return ConfigError::get("Couldn't parse color_map.*.color"),
-                    },
-                };
-                let scale = match json["color_map"][index]["scale"].as_f64() {
-                    Some(val) => val,
-                    None => 1.0,
+            match json["resize_filter"].as_str().and_then(ResizeFilter::from_name) {
+                Some(val) => val,
+                None => return Err(DitherError::config("resize_filter", "not recognized")),
+            }
+        };
+        let fit = if json["fit"].is_null() {
+            FitMode::default()
+        } else {
+            match json["fit"].as_str().and_then(FitMode::from_name) {
+                Some(val) => val,
+                None => return Err(DitherError::config("fit", "not recognized")),
+            }
+        };
+        let crop = if json["crop"].is_null() {
+            None
+        } else {
+            match parse_crop(&json["crop"]) {
+                Some(val) => Some(val),
+                None => return Err(DitherError::config("crop", "couldn't parse")),
+            }
+        };
+        // a single factor, or a different one for each axis
+        let output_scale = match (
+            json["output_scale"].as_f64(),
+            json["output_scale"]["x"].as_f64(),
+            json["output_scale"]["y"].as_f64(),
+        ) {
+            (Some(scale), _, _) if scale > 0.0 => OutputScale::uniform(scale),
+            (None, Some(x), Some(y)) if x > 0.0 && y > 0.0 => OutputScale { x, y },
+            _ => return Err(DitherError::config("output_scale", "couldn't parse")),
+        };
+
+        let dithering_type = parse_dithering_type(&json)?;
+
+        let seed: Option<u64> = if json["seed"].is_null() {
+            None
+        } else {
+            match json["seed"].as_u64() {
+                Some(val) => Some(val),
+                None => return Err(DitherError::config("seed", "couldn't parse")),
+            }
+        };
+        let noise = if json["noise"].is_null() {
+            NoiseDistribution::default()
+        } else {
+            match json["noise"].as_str().and_then(NoiseDistribution::from_name) {
+                Some(val) => val,
+                None => return Err(DitherError::config("noise", "not recognized")),
+            }
+        };
+        let hashed_noise = json["hashed_noise"].as_bool().unwrap_or(false);
+        let tileable = json["tileable"].as_bool().unwrap_or(false);
+
+        // "dbs": true for the default settings, or an object overriding some of them
+        let dbs = match (json["dbs"].is_null(), json["dbs"].as_bool()) {
+            (true, _) | (_, Some(false)) => None,
+            (_, Some(true)) => Some(DbsOptions::default()),
+            (_, None) if json["dbs"].is_object() => {
+                let default = DbsOptions::default();
+                Some(DbsOptions {
+                    iterations: json["dbs"]["iterations"].as_u32().unwrap_or(default.iterations),
+                    convergence: json["dbs"]["convergence"].as_f64().unwrap_or(default.convergence),
+                    filter_width: json["dbs"]["filter_width"].as_f64().unwrap_or(default.filter_width),
+                })
+            }
+            (_, None) => return Err(DitherError::config("dbs", "couldn't parse")),
+        };
+
+        // the same goes for "stipple"
+        let stipple = match (json["stipple"].is_null(), json["stipple"].as_bool()) {
+            (true, _) | (_, Some(false)) => None,
+            (_, Some(true)) => Some(StippleOptions::default()),
+            (_, None) if json["stipple"].is_object() => {
+                let default = StippleOptions::default();
+                Some(StippleOptions {
+                    points: json["stipple"]["points"].as_usize().unwrap_or(default.points),
+                    iterations: json["stipple"]["iterations"].as_u32().unwrap_or(default.iterations),
+                    min_radius: json["stipple"]["min_radius"].as_f64().unwrap_or(default.min_radius),
+                    max_radius: json["stipple"]["max_radius"].as_f64().unwrap_or(default.max_radius),
+                })
+            }
+            (_, None) => return Err(DitherError::config("stipple", "couldn't parse")),
+        };
+
+        // and for "edges"
+        let edges = match (json["edges"].is_null(), json["edges"].as_bool()) {
+            (true, _) | (_, Some(false)) => None,
+            (_, Some(true)) => Some(EdgeOptions::default()),
+            (_, None) if json["edges"].is_object() => {
+                let default = EdgeOptions::default();
+                let detector = if json["edges"]["detector"].is_null() {
+                    default.detector
+                } else {
+                    match json["edges"]["detector"].as_str().and_then(EdgeDetector::from_name) {
+                        Some(val) => val,
+                        None => return Err(DitherError::config("edges.detector", "not recognized")),
+                    }
                 };
-                let offset = match json["color_map"][index]["offset"].as_f64() {
-                    Some(val) => val,
-                    None => 0.0,
+                let color = match json["edges"]["color"].as_str() {
+                    Some(val) => Some(RGB::from_hex(val.to_string())?),
+                    None => None,
                 };
+                Some(EdgeOptions {
+                    detector,
+                    threshold: json["edges"]["threshold"].as_f64().unwrap_or(default.threshold),
+                    thickness: json["edges"]["thickness"].as_u32().unwrap_or(default.thickness),
+                    sigma: json["edges"]["sigma"].as_f64().unwrap_or(default.sigma),
+                    color,
+                    only: json["edges"]["only"].as_bool().unwrap_or(default.only),
+                })
+            }
+            (_, None) => return Err(DitherError::config("edges", "couldn't parse")),
+        };
+
+        let color_map = parse_color_map(&json)?;
 
-                color_map.push(ColorMapElement {
-                    color: RGB::from_hex(color)?,
-                    scale: scale,
-                    offset: offset,
-                });
+        // "alpha": the name of the mode, or an object with "mode" and its parameter
+        let alpha = if json["alpha"].is_null() {
+            None
+        } else {
+            let mode = json["alpha"].as_str().or(json["alpha"]["mode"].as_str());
+            let alpha = match mode {
+                Some("threshold") => AlphaMode::Threshold(json["alpha"]["threshold"].as_f64().unwrap_or(0.5)),
+                Some("dither") => AlphaMode::Dither,
+                Some("background") => match json["alpha"]["color"].as_str() {
+                    Some(hex) => AlphaMode::Background(Some(RGB::from_hex(hex.to_string())?)),
+                    None => AlphaMode::Background(None),
+                },
+                _ => return Err(DitherError::config("alpha", "not recognized")),
+            };
+            Some(alpha)
+        };
 
-                index += 1;
+        let mut spot_colors: Vec<SpotColor> = Vec::new();
+        for (index, spot) in json["spot_colors"].members().enumerate() {
+            match parse_spot_color(spot) {
+                Some(val) => spot_colors.push(val),
+                None => return Err(DitherError::config(&format!("spot_colors.{}", index), "couldn't parse")),
             }
-            color_map
+        }
+
+        // the masked region takes what it doesn't override from the rest of the config
+        let mask = if json["mask"].is_null() {
+            None
+        } else {
+            let path = match json["mask"]["path"].as_str() {
+                Some(val) => val.to_string(),
+                None => return Err(DitherError::config("mask.path", "couldn't parse")),
+            };
+            // read as it is, the grey levels are the weights
+            let image = ImageReader::open(&path)?.decode()?;
+            let overrides_type = ["dithering_type", "threshold_expr", "class_matrix"]
+                .iter()
+                .any(|field| !json["mask"][*field].is_null());
+            Some(Mask {
+                path,
+                image,
+                soft: json["mask"]["soft"].as_bool().unwrap_or(false),
+                dithering_type: if overrides_type {
+                    parse_dithering_type(&json["mask"])?
+                } else {
+                    dithering_type.clone()
+                },
+                color_map: if json["mask"]["color_map"].is_null() {
+                    color_map.clone()
+                } else {
+                    parse_color_map(&json["mask"])?
+                },
+                brigthness_delta: json["mask"]["brigthness_delta"].as_i32().unwrap_or(brigthness_delta),
+                constrast_delta: json["mask"]["constrast_delta"].as_f32().unwrap_or(constrast_delta),
+            })
+        };
+
+        let importance = if json["importance"].is_null() {
+            None
+        } else {
+            let default = Importance::default();
+            let path = match json["importance"]["path"].as_str() {
+                Some(val) => val.to_string(),
+                None => return Err(DitherError::config("importance.path", "couldn't parse")),
+            };
+            let image = ImageReader::open(&path)?.decode()?;
+            Some(Importance {
+                path,
+                image,
+                invert: json["importance"]["invert"].as_bool().unwrap_or(default.invert),
+                blur: json["importance"]["blur"].as_f64().unwrap_or(default.blur),
+                contrast: json["importance"]["contrast"].as_f64().unwrap_or(default.contrast),
+                threshold_scale: json["importance"]["threshold_scale"].as_f64().unwrap_or(default.threshold_scale),
+                pattern_size: json["importance"]["pattern_size"].as_f64().unwrap_or(default.pattern_size),
+            })
         };
 
-        Ok(ProcessConfig {
-            brigthness_delta: brigthness_delta,
-            constrast_delta: constrast_delta,
-            dithering_type: dithering_type,
-            color_map: color_map,
-            processing_width: processing_width,
-            processing_height: processing_height,
-            output_scale: output_scale,
-        })
+        // the canvas color is a hex color or the index of a color of the color map
+        let pad = if json["pad"].is_null() {
+            None
+        } else {
+            let (width, height) = match (json["pad"]["width"].as_u32(), json["pad"]["height"].as_u32()) {
+                (Some(width), Some(height)) if width > 0 && height > 0 => (width, height),
+                _ => return Err(DitherError::config("pad", "couldn't parse")),
+            };
+            let color = match (json["pad"]["color"].as_usize(), json["pad"]["color"].as_str()) {
+                (Some(index), _) => match color_map.get(index) {
+                    Some(element) => Some(element.color),
+                    None => return Err(DitherError::config("pad.color", "should be the index of a color of the color_map")),
+                },
+                (None, Some(hex)) => Some(RGB::from_hex(hex.to_string())?),
+                (None, None) => None,
+            };
+            Some(Pad { width, height, color })
+        };
+
+        let config = ProcessConfig {
+            filters,
+            auto_tone,
+            brigthness_delta,
+            constrast_delta,
+            dithering_type,
+            color_map,
+            processing_width,
+            processing_height,
+            resize_filter,
+            fit,
+            crop,
+            output_scale,
+            pad,
+            seed,
+            noise,
+            hashed_noise,
+            tileable,
+            dbs,
+            stipple,
+            edges,
+            alpha,
+            spot_colors,
+            mask,
+            importance,
+        };
+        config.validate()?;
+        Ok(config)
     }
 
     fn to_json(config: &ProcessConfig) -> String {
         let mut data = json::JsonValue::new_object();
 
+        if !config.filters.is_empty() {
+            data["filters"] = config.filters.clone().into();
+        }
+        if let Some(auto_tone) = config.auto_tone {
+            data["auto_tone"] = object! {
+                clip: auto_tone.clip,
+                tiles: auto_tone.tiles,
+                clip_limit: auto_tone.clip_limit
+            };
+        }
+
         data["brigthness_delta"] = config.brigthness_delta.into();
         data["constrast_delta"] = config.constrast_delta.into();
-        data["dithering_type"] = config.dithering_type.into();
+        write_dithering_type(&mut data, &config.dithering_type);
         data["color_map"] = config.color_map.clone().into();
         data["processing_width"] = config.processing_width.into();
         data["processing_height"] = config.processing_height.into();
-        data["output_scale"] = config.output_scale.into();
+        data["resize_filter"] = config.resize_filter.name().into();
+        data["fit"] = config.fit.name().into();
+        if let Some(crop) = config.crop {
+            data["crop"] = crop.into();
+        }
+        data["output_scale"] = if config.output_scale.x == config.output_scale.y {
+            config.output_scale.x.into()
+        } else {
+            object! {
+                x: config.output_scale.x,
+                y: config.output_scale.y
+            }
+        };
+        if let Some(pad) = config.pad {
+            data["pad"] = object! {
+                width: pad.width,
+                height: pad.height
+            };
+            if let Some(color) = pad.color {
+                data["pad"]["color"] = color.to_hex().into();
+            }
+        }
+        if let Some(seed) = config.seed {
+            data["seed"] = seed.into();
+        }
+        data["noise"] = config.noise.name().into();
+        data["hashed_noise"] = config.hashed_noise.into();
+        data["tileable"] = config.tileable.into();
+        if let Some(dbs) = config.dbs {
+            data["dbs"] = object! {
+                iterations: dbs.iterations,
+                convergence: dbs.convergence,
+                filter_width: dbs.filter_width
+            };
+        }
+        if let Some(stipple) = config.stipple {
+            data["stipple"] = object! {
+                points: stipple.points,
+                iterations: stipple.iterations,
+                min_radius: stipple.min_radius,
+                max_radius: stipple.max_radius
+            };
+        }
+        if let Some(edges) = config.edges {
+            data["edges"] = object! {
+                detector: edges.detector.name(),
+                threshold: edges.threshold,
+                thickness: edges.thickness,
+                sigma: edges.sigma,
+                only: edges.only
+            };
+            if let Some(color) = edges.color {
+                data["edges"]["color"] = color.into();
+            }
+        }
+
+        if let Some(alpha) = config.alpha {
+            data["alpha"] = object! { mode: alpha.name() };
+            match alpha {
+                AlphaMode::Threshold(threshold) => data["alpha"]["threshold"] = threshold.into(),
+                AlphaMode::Background(Some(color)) => data["alpha"]["color"] = color.into(),
+                AlphaMode::Dither | AlphaMode::Background(None) => {}
+            }
+        }
+        if !config.spot_colors.is_empty() {
+            data["spot_colors"] = config.spot_colors.clone().into();
+        }
+        if let Some(mask) = &config.mask {
+            data["mask"] = object! {
+                path: mask.path.clone(),
+                soft: mask.soft,
+                color_map: mask.color_map.clone(),
+                brigthness_delta: mask.brigthness_delta,
+                constrast_delta: mask.constrast_delta
+            };
+            write_dithering_type(&mut data["mask"], &mask.dithering_type);
+        }
+        if let Some(importance) = &config.importance {
+            data["importance"] = object! {
+                path: importance.path.clone(),
+                invert: importance.invert,
+                blur: importance.blur,
+                contrast: importance.contrast,
+                threshold_scale: importance.threshold_scale,
+                pattern_size: importance.pattern_size
+            };
+        }
 
         data.to_string()
     }
 
-    pub fn read_config(path: &String) -> Result<ProcessConfig, Box<dyn std::error::Error>> {
+    pub fn dither_options(&self) -> DitherOptions {
+        DitherOptions {
+            seed: self.seed,
+            noise: self.noise,
+            hashed_noise: self.hashed_noise,
+            tileable: self.tileable,
+        }
+    }
+
+    // what the parsing already makes sure of, for configs built or changed in code: the settings
+    // processing can't work without
+    pub fn validate(&self) -> Result<(), DitherError> {
+        if self.color_map.len() < 2 {
+            return Err(DitherError::Palette("a color map needs 2 or more colors".to_string()));
+        }
+        if self.mask.as_ref().is_some_and(|mask| mask.color_map.len() < 2) {
+            return Err(DitherError::Palette("the color map of the mask needs 2 or more colors".to_string()));
+        }
+        if self.processing_width == 0 || self.processing_height == 0 {
+            return Err(DitherError::dimensions(
+                self.processing_width,
+                self.processing_height,
+                "the processing size should be at least 1x1",
+            ));
+        }
+        let scale = [self.output_scale.x, self.output_scale.y];
+        if scale.iter().any(|scale| !scale.is_finite() || *scale <= 0.0) {
+            return Err(DitherError::config("output_scale", "should be positive"));
+        }
+        if let Some(pad) = self.pad.filter(|pad| pad.width == 0 || pad.height == 0) {
+            return Err(DitherError::dimensions(pad.width, pad.height, "the pad canvas should be at least 1x1"));
+        }
+        if let Some(pad) = self.pad.filter(|pad| pad.width as u64 * pad.height as u64 > MAX_PIXELS) {
+            return Err(DitherError::dimensions(
+                pad.width,
+                pad.height,
+                &format!("the pad canvas is larger than {} pixels", MAX_PIXELS),
+            ));
+        }
+        self.check_size(self.processing_width, self.processing_height)?;
+        // both sides of a mask are dithered on their own, with neither a refinement nor an
+        // importance map
+        if self.mask.is_some() && self.dbs.is_some() {
+            return Err(DitherError::config("mask", "can't be used along with dbs"));
+        }
+        if self.mask.is_some() && self.importance.is_some() {
+            return Err(DitherError::config("mask", "can't be used along with importance"));
+        }
+        if let Some(stipple) = &self.stipple {
+            for (field, radius) in [("stipple.min_radius", stipple.min_radius), ("stipple.max_radius", stipple.max_radius)] {
+                if !radius.is_finite() || radius < 0.0 {
+                    return Err(DitherError::config(field, "should be positive"));
+                }
+            }
+            if stipple.min_radius > stipple.max_radius {
+                return Err(DitherError::config("stipple.min_radius", "should be at most max_radius"));
+            }
+            if stipple.points as u64 > MAX_PIXELS {
+                return Err(DitherError::config("stipple.points", &format!("should be at most {}", MAX_PIXELS)));
+            }
+        }
+        // a blur wider than the image only averages it further, and its kernel has to be allocated
+        let longest = self.processing_width.max(self.processing_height) as f64;
+        let sigmas = self
+            .filters
+            .iter()
+            .filter_map(|filter| match filter {
+                Filter::UnsharpMask { sigma, .. } | Filter::Blur { sigma } => Some(("filters", *sigma)),
+                _ => None,
+            })
+            .chain(self.edges.as_ref().map(|edges| ("edges.sigma", edges.sigma)))
+            .chain(self.importance.as_ref().map(|importance| ("importance.blur", importance.blur)))
+            .chain(self.dbs.as_ref().map(|dbs| ("dbs.filter_width", dbs.filter_width)));
+        for (field, sigma) in sigmas {
+            if !sigma.is_finite() || sigma > longest {
+                return Err(DitherError::config(field, &format!("should be at most the processing size, {}", longest)));
+            }
+        }
+        if self.tileable {
+            let mut types = std::iter::once(&self.dithering_type).chain(self.mask.as_ref().map(|mask| &mask.dithering_type));
+            if let Some(dtype) = types.find(|dtype| !dtype.wraps()) {
+                return Err(DitherError::config("tileable", &format!("{} doesn't wrap", dtype.name())));
+            }
+            if self.dbs.is_some() {
+                return Err(DitherError::config("tileable", "dbs doesn't wrap"));
+            }
+        }
+        Ok(())
+    }
+
+    // the image prepared at this size, and the output made from it, fit in MAX_PIXELS. the
+    // processing size bounds most prepared images, the width fit can go past it
+    pub fn check_size(&self, width: u32, height: u32) -> Result<(), DitherError> {
+        if width as u64 * height as u64 > MAX_PIXELS {
+            return Err(DitherError::dimensions(
+                width,
+                height,
+                &format!("the processed image is larger than {} pixels", MAX_PIXELS),
+            ));
+        }
+        let (width, height) = self.pad.map_or((width, height), |pad| (pad.width, pad.height));
+        let output = (width as f64 * self.output_scale.x).round() * (height as f64 * self.output_scale.y).round();
+        if output > MAX_PIXELS as f64 {
+            return Err(DitherError::dimensions(
+                width,
+                height,
+                &format!("scaled by output_scale, the output is larger than {} pixels", MAX_PIXELS),
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn read_config(path: &String) -> Result<ProcessConfig, DitherError> {
         let mut file = File::open(path)?;
         let mut buff: Vec<u8> = Vec::new();
         let _ = file.read_to_end(&mut buff)?;
 
-        let json_string = String::from_utf8(buff)?;
+        let json_string = match String::from_utf8(buff) {
+            Ok(json_string) => json_string,
+            Err(_) => return Err(DitherError::config("", "not utf-8 text")),
+        };
 
         ProcessConfig::to_config(json_string)
     }
 
-    pub fn write_config(&self, path: String) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn write_config(&self, path: String) -> Result<(), DitherError> {
         let string = ProcessConfig::to_json(self);
         let mut file = File::create(path)?;
         file.write_all(string.as_bytes())?;
@@ -143,19 +600,263 @@ impl ProcessConfig {
     }
 }
 
-impl From<DitheringType> for JsonValue {
-    fn from(dtype: DitheringType) -> Self {
-        match dtype {
-            DitheringType::Rand => JsonValue::String(String::from("rand")),
-            DitheringType::Bayer0 => JsonValue::String(String::from("bayer_0")),
-            DitheringType::Bayer1 => JsonValue::String(String::from("bayer_1")),
-            DitheringType::Bayer2 => JsonValue::String(String::from("bayer_2")),
-            DitheringType::Bayer3 => JsonValue::String(String::from("bayer_3")),
-            DitheringType::BlueNoise => JsonValue::String(String::from("blue_noise")),
-            DitheringType::Atkinson => JsonValue::String(String::from("atkinson")),
-            DitheringType::JarvisJudiceNinke => JsonValue::String(String::from("jarvis")),
-            DitheringType::FloydSteinberg => JsonValue::String(String::from("floyd")),
+// the dithering type of a config object, along with its threshold expression or class matrix
+fn parse_dithering_type(json: &JsonValue) -> Result<DitheringType, DitherError> {
+    // a threshold expression makes "dithering_type" optional, or "expr", the same goes for a
+    // class matrix and "dot_diffusion"
+    if !json["class_matrix"].is_null() {
+        let other_type = json["dithering_type"].as_str().is_some_and(|name| name != "dot_diffusion");
+        if other_type || !json["threshold_expr"].is_null() {
+            return Err(DitherError::config("class_matrix", "only used with the \"dot_diffusion\" dithering type"));
+        }
+    }
+    let dithering_type = match (
+        json["dithering_type"].as_str(),
+        json["threshold_expr"].as_str(),
+    ) {
+        (None | Some("dot_diffusion"), None) if !json["class_matrix"].is_null() => {
+            match parse_class_matrix(&json["class_matrix"]) {
+                Some(class_matrix) => DitheringType::CustomDotDiffusion(class_matrix),
+                None => return Err(DitherError::config(
+                    "class_matrix",
+                    "should be rows of equal length, using every class from 0 to n-1 once",
+                )),
+            }
+        }
+        (None | Some("expr"), Some(expr)) => match ThresholdExpr::parse(expr) {
+            Ok(expr) => DitheringType::Expression(expr),
+            Err(error) => return Err(DitherError::config("threshold_expr", &error.to_string())),
+        },
+        (Some("expr"), None) => return Err(DitherError::config("threshold_expr", "couldn't parse")),
+        (Some(_), Some(_)) => {
+            return Err(DitherError::config("threshold_expr", "only used with the \"expr\" dithering type"))
+        }
+        (Some(s), _) => match DitheringType::from_name(s) {
+            Some(dtype) => dtype,
+            None => return Err(DitherError::config("dithering_type", "not recognized")),
+        },
+        (None, None) => return Err(DitherError::config("dithering_type", "couldn't parse")),
+    };
+    Ok(dithering_type)
+}
+
+fn write_dithering_type(data: &mut JsonValue, dithering_type: &DitheringType) {
+    data["dithering_type"] = dithering_type.name().into();
+    if let DitheringType::Expression(expr) = dithering_type {
+        data["threshold_expr"] = expr.source().into();
+    }
+    if let DitheringType::CustomDotDiffusion(class_matrix) = dithering_type {
+        data["class_matrix"] = class_matrix.rows().into();
+    }
+}
+
+fn parse_color_map(json: &JsonValue) -> Result<Vec<ColorMapElement>, DitherError> {
+    let color_map = if json["color_map"].is_null() {
+        DEFAULT_COLOR_MAP.to_vec()
+    } else if json["color_map"].len() <= 1 {
+        return Err(DitherError::config("color_map", "should be an array of 2 or more colors objects"));
+    } else {
+        let mut index = 0;
+        let mut color_map: Vec<ColorMapElement> = Vec::new();
+        while index < json["color_map"].len() {
+            let color = match json["color_map"][index]["color"].as_str() {
+                Some(val) => val.to_string(),
+                None => match json["color_map"][index].as_str() {
+                    Some(val) => val.to_string(),
+                    None => return Err(DitherError::config("color_map.*.color", "couldn't parse")),
+                },
+            };
+            let scale = json["color_map"][index]["scale"]
+                .as_f64()
+                .unwrap_or(1.0);
+            let offset = json["color_map"][index]["offset"]
+                .as_f64()
+                .unwrap_or(0.0);
+
+            color_map.push(ColorMapElement {
+                color: RGB::from_hex(color)?,
+                scale,
+                offset,
+            });
+
+            index += 1;
+        }
+        color_map
+    };
+    Ok(color_map)
+}
+
+fn parse_class_matrix(json: &JsonValue) -> Option<ClassMatrix> {
+    let rows = json
+        .members()
+        .map(|row| row.members().map(|class| class.as_usize()).collect::<Option<Vec<usize>>>())
+        .collect::<Option<Vec<Vec<usize>>>>()?;
+    ClassMatrix::new(&rows)
+}
+
+// a rectangle in pixels, or in percents with "unit": "percent", or an aspect ratio ("4:3" or a
+// number) around a focal point
+fn parse_crop(json: &JsonValue) -> Option<Crop> {
+    if !json["aspect"].is_null() {
+        let ratio = match json["aspect"].as_str() {
+            Some(aspect) => {
+                let (width, height) = aspect.split_once(':')?;
+                width.trim().parse::<f64>().ok()? / height.trim().parse::<f64>().ok()?
+            }
+            None => json["aspect"].as_f64()?,
+        };
+        if !(ratio.is_finite() && ratio > 0.0) {
+            return None;
+        }
+        return Some(Crop::Aspect {
+            ratio,
+            focus_x: json["focus_x"].as_f64().unwrap_or(0.5).clamp(0.0, 1.0),
+            focus_y: json["focus_y"].as_f64().unwrap_or(0.5).clamp(0.0, 1.0),
+        });
+    }
+
+    let crop = match json["unit"].as_str().unwrap_or("px") {
+        "px" => Crop::Rect {
+            x: json["x"].as_u32().unwrap_or(0),
+            y: json["y"].as_u32().unwrap_or(0),
+            width: json["width"].as_u32()?,
+            height: json["height"].as_u32()?,
+        },
+        "percent" => Crop::Percent {
+            x: json["x"].as_f64().unwrap_or(0.0),
+            y: json["y"].as_f64().unwrap_or(0.0),
+            width: json["width"].as_f64()?,
+            height: json["height"].as_f64()?,
+        },
+        _ => return None,
+    };
+    Some(crop)
+}
+
+impl From<Crop> for JsonValue {
+    fn from(crop: Crop) -> Self {
+        match crop {
+            Crop::Rect { x, y, width, height } => object! { x: x, y: y, width: width, height: height },
+            Crop::Percent { x, y, width, height } => {
+                object! { unit: "percent", x: x, y: y, width: width, height: height }
+            }
+            Crop::Aspect { ratio, focus_x, focus_y } => {
+                object! { aspect: ratio, focus_x: focus_x, focus_y: focus_y }
+            }
+        }
+    }
+}
+
+// "hue" is required, along with "color" as hex when the accent isn't the pure hue
+fn parse_spot_color(json: &JsonValue) -> Option<SpotColor> {
+    let default = SpotColor::default();
+    let color = match json["color"].as_str() {
+        Some(hex) => Some(RGB::from_hex(hex.to_string()).ok()?),
+        None => None,
+    };
+    Some(SpotColor {
+        hue: json["hue"].as_f64()?,
+        hue_tolerance: json["hue_tolerance"].as_f64().unwrap_or(default.hue_tolerance),
+        min_chroma: json["min_chroma"].as_f64().unwrap_or(default.min_chroma),
+        color,
+        dither: json["dither"].as_bool().unwrap_or(default.dither),
+    })
+}
+
+impl From<SpotColor> for JsonValue {
+    fn from(spot: SpotColor) -> Self {
+        let mut data = object! {
+            hue: spot.hue,
+            hue_tolerance: spot.hue_tolerance,
+            min_chroma: spot.min_chroma,
+            dither: spot.dither
+        };
+        if let Some(color) = spot.color {
+            data["color"] = color.into();
+        }
+        data
+    }
+}
+
+// a filter is either its name, or an object with its name in "filter" and its parameters
+fn parse_filter(json: &JsonValue) -> Option<Filter> {
+    let name = json.as_str().or(json["filter"].as_str())?;
+    let filter = match name {
+        "gamma" => Filter::Gamma {
+            gamma: json["gamma"].as_f64()?,
+        },
+        "levels" => Filter::Levels {
+            black: json["black"].as_f64().unwrap_or(0.0),
+            white: json["white"].as_f64().unwrap_or(1.0),
+        },
+        "auto_levels" => Filter::AutoLevels {
+            low: json["low"].as_f64().unwrap_or(0.01),
+            high: json["high"].as_f64().unwrap_or(0.99),
+        },
+        "curves" => {
+            let points = json["points"]
+                .members()
+                .map(|point| Some((point[0].as_f64()?, point[1].as_f64()?)))
+                .collect::<Option<Vec<(f64, f64)>>>()?;
+            if points.len() < 2 {
+                return None;
+            }
+            Filter::Curves { points }
+        }
+        "unsharp_mask" => Filter::UnsharpMask {
+            sigma: json["sigma"].as_f64().unwrap_or(1.0),
+            amount: json["amount"].as_f64().unwrap_or(1.0),
+        },
+        "blur" => Filter::Blur {
+            sigma: json["sigma"].as_f64().unwrap_or(1.0),
+        },
+        "equalize" => Filter::Equalize,
+        "clahe" => Filter::Clahe {
+            tiles: json["tiles"].as_u32().unwrap_or(8),
+            clip_limit: json["clip_limit"].as_f64().unwrap_or(2.0),
+        },
+        "posterize" => Filter::Posterize {
+            levels: json["levels"].as_u32()?,
+        },
+        "invert" => Filter::Invert,
+        "saturation" => Filter::Saturation {
+            amount: json["amount"].as_f64()?,
+        },
+        _ => return None,
+    };
+    Some(filter)
+}
+
+impl From<Filter> for JsonValue {
+    fn from(filter: Filter) -> Self {
+        let mut data = object! { filter: filter.name() };
+        match filter {
+            Filter::Gamma { gamma } => data["gamma"] = gamma.into(),
+            Filter::Levels { black, white } => {
+                data["black"] = black.into();
+                data["white"] = white.into();
+            }
+            Filter::AutoLevels { low, high } => {
+                data["low"] = low.into();
+                data["high"] = high.into();
+            }
+            Filter::Curves { points } => {
+                data["points"] = points.iter().map(|(x, y)| vec![*x, *y]).collect::<Vec<Vec<f64>>>().into()
+            }
+            Filter::UnsharpMask { sigma, amount } => {
+                data["sigma"] = sigma.into();
+                data["amount"] = amount.into();
+            }
+            Filter::Blur { sigma } => data["sigma"] = sigma.into(),
+            Filter::Clahe { tiles, clip_limit } => {
+                data["tiles"] = tiles.into();
+                data["clip_limit"] = clip_limit.into();
+            }
+            Filter::Posterize { levels } => data["levels"] = levels.into(),
+            Filter::Saturation { amount } => data["amount"] = amount.into(),
+            Filter::Equalize | Filter::Invert => {}
         }
+        data
     }
 }
 
@@ -171,22 +872,220 @@ impl From<ColorMapElement> for JsonValue {
     }
 }
 
-#[derive(Debug)]
-pub struct ConfigError {
-    msg: String,
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-impl ConfigError {
-    fn get(msg: &str) -> Result<ProcessConfig, Box<dyn std::error::Error>> {
-        Err(Box::new(ConfigError {
-            msg: String::from(msg),
-        }))
+    #[test]
+    fn test_json_round_trip() {
+        let json = r##"{
+            "processing_width": 300,
+            "processing_height": 200,
+            "brigthness_delta": -10,
+            "constrast_delta": 30,
+            "dithering_type": "rand",
+            "color_map": ["101010", { "color": "#0000aa", "offset": 0.15, "scale": 0.85 }, "f0f0f0"],
+            "output_scale": { "x": 2.5, "y": 1.5 },
+            "resize_filter": "lanczos3",
+            "fit": "fill",
+            "crop": { "aspect": "16:9", "focus_x": 0.25 },
+            "pad": { "width": 320, "height": 200, "color": 2 },
+            "seed": 42,
+            "noise": "triangular",
+            "hashed_noise": true,
+            "tileable": false,
+            "dbs": { "iterations": 4, "filter_width": 2.0 },
+            "stipple": { "points": 500 },
+            "edges": { "detector": "canny", "color": "aa0000", "thickness": 2 },
+            "filters": [
+                { "filter": "levels", "black": 0.1, "white": 0.9 },
+                { "filter": "curves", "points": [[0, 0], [0.5, 0.6], [1, 1]] },
+                "invert",
+                { "filter": "clahe", "tiles": 4 },
+                "auto_levels"
+            ],
+            "auto_tone": { "tiles": 0 },
+            "alpha": { "mode": "threshold", "threshold": 0.25 },
+            "spot_colors": [{ "hue": 350, "color": "#e02020", "dither": true }, { "hue": 200, "min_chroma": 0.4 }]
+        }"##;
+
+        let config = ProcessConfig::to_config(json.to_string()).unwrap();
+        let again = ProcessConfig::to_config(ProcessConfig::to_json(&config)).unwrap();
+
+        assert_eq!(format!("{:?}", config), format!("{:?}", again));
+        assert_eq!(config.seed, Some(42));
+        assert_eq!(config.noise, NoiseDistribution::Triangular);
+        assert_eq!(config.color_map[1].color.to_hex(), "0000AA");
+        assert_eq!(config.dbs.map(|dbs| dbs.iterations), Some(4));
+        assert_eq!(config.stipple.map(|stipple| stipple.points), Some(500));
+        assert_eq!(config.edges.map(|edges| edges.detector), Some(EdgeDetector::Canny));
+        assert_eq!(config.filters.len(), 5);
+        assert_eq!(config.auto_tone.map(|auto_tone| auto_tone.filters().len()), Some(1));
+        assert_eq!(config.filters[2], Filter::Invert);
+        assert_eq!(config.alpha, Some(AlphaMode::Threshold(0.25)));
+        assert_eq!(config.spot_colors.len(), 2);
+        assert_eq!(config.spot_colors[0].color.map(|color| color.to_hex()), Some("E02020".to_string()));
+        assert_eq!((config.spot_colors[1].hue_tolerance, config.spot_colors[1].min_chroma), (20.0, 0.4));
+        assert_eq!(config.resize_filter, ResizeFilter::Lanczos3);
+        assert_eq!(config.fit, FitMode::Fill);
+        assert_eq!(config.output_scale.apply(100, 100), (250, 150));
+        assert_eq!(config.crop.map(|crop| crop.area(1600, 1600)), Some((0, 350, 1600, 900)));
+        assert_eq!(config.pad.and_then(|pad| pad.color), Some(config.color_map[2].color));
+
+        let broken = json.replace(r#""invert""#, r#""inverse""#);
+        assert!(ProcessConfig::to_config(broken).is_err());
+
+        // dbs doesn't wrap around the borders
+        let tileable = json.replace(r#""tileable": false"#, r#""tileable": true"#);
+        assert!(matches!(ProcessConfig::to_config(tileable.clone()), Err(DitherError::Config { .. })));
+        let tileable = tileable.replace(r#""dbs": { "iterations": 4, "filter_width": 2.0 },"#, "");
+        let config = ProcessConfig::to_config(tileable).unwrap();
+        let again = ProcessConfig::to_config(ProcessConfig::to_json(&config)).unwrap();
+        assert!(again.tileable);
+        assert_eq!(format!("{:?}", config), format!("{:?}", again));
     }
-}
 
-impl Display for ConfigError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_fmt(format_args!("ConfigParseError {}", self.msg))
+    #[test]
+    fn test_threshold_expr() {
+        let json = r#"{
+            "processing_width": 300,
+            "processing_height": 200,
+            "brigthness_delta": 0,
+            "constrast_delta": 0,
+            "threshold_expr": "fract((x + y) / 6)",
+            "output_scale": 4
+        }"#;
+
+        let config = ProcessConfig::to_config(json.to_string()).unwrap();
+        let again = ProcessConfig::to_config(ProcessConfig::to_json(&config)).unwrap();
+        match again.dithering_type {
+            DitheringType::Expression(expr) => assert_eq!(expr.source(), "fract((x + y) / 6)"),
+            dtype => panic!("expected an expression, got {}", dtype.name()),
+        }
+
+        let broken = json.replace("fract((x + y) / 6)", "fract((x + y) / 6");
+        assert!(ProcessConfig::to_config(broken).is_err());
+        // the expression would be ignored by another dithering type
+        let conflicting = json.replace(r#""threshold_expr""#, r#""dithering_type": "bayer_3", "threshold_expr""#);
+        match ProcessConfig::to_config(conflicting) {
+            Err(DitherError::Config { field, .. }) => assert_eq!(field, "threshold_expr"),
+            other => panic!("expected a config error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_class_matrix() {
+        let json = r#"{
+            "processing_width": 300,
+            "processing_height": 200,
+            "brigthness_delta": 0,
+            "constrast_delta": 0,
+            "dithering_type": "dot_diffusion",
+            "class_matrix": [[0, 2], [3, 1]],
+            "output_scale": 4
+        }"#;
+
+        let config = ProcessConfig::to_config(json.to_string()).unwrap();
+        let again = ProcessConfig::to_config(ProcessConfig::to_json(&config)).unwrap();
+        match again.dithering_type {
+            DitheringType::CustomDotDiffusion(class_matrix) => {
+                assert_eq!(class_matrix.rows(), vec![vec![0, 2], vec![3, 1]])
+            }
+            dtype => panic!("expected a custom class matrix, got {}", dtype.name()),
+        }
+
+        let broken = json.replace("[3, 1]", "[3, 3]");
+        assert!(ProcessConfig::to_config(broken).is_err());
+
+        // the matrix would be ignored by another dithering type or an expression
+        let conflicting = [
+            json.replace(r#""dot_diffusion""#, r#""bayer_3""#),
+            json.replace(r#""dithering_type": "dot_diffusion""#, r#""threshold_expr": "fract(x / 3)""#),
+        ];
+        for conflicting in conflicting {
+            match ProcessConfig::to_config(conflicting) {
+                Err(DitherError::Config { field, .. }) => assert_eq!(field, "class_matrix"),
+                other => panic!("expected a config error, got {:?}", other.map(|_| ())),
+            }
+        }
+    }
+
+    #[test]
+    fn test_mask_and_importance() {
+        let path = std::env::temp_dir().join("ditherpunker_test_mask.png");
+        image::GrayImage::from_fn(4, 4, |x, _| image::Luma([x as u8 * 85])).save(&path).unwrap();
+        let json = r#"{
+            "processing_width": 300,
+            "processing_height": 200,
+            "brigthness_delta": 5,
+            "constrast_delta": 0,
+            "dithering_type": "blue_noise",
+            "output_scale": 4,
+            "mask": { "path": "PATH", "threshold_expr": "fract(x / 3)", "constrast_delta": 20 },
+            "importance": { "path": "PATH", "invert": true, "pattern_size": 3 }
+        }"#
+        .replace("PATH", path.to_str().unwrap());
+        // each side of a mask is dithered on its own, without the importance map
+        assert!(matches!(ProcessConfig::to_config(json.clone()), Err(DitherError::Config { .. })));
+        let masked = json.replace(r#""importance""#, r#""unused""#);
+        let weighted = json.replace(r#""mask""#, r#""unused""#);
+
+        let config = ProcessConfig::to_config(masked.clone()).unwrap();
+        let mask = config.mask.as_ref().unwrap();
+        assert_eq!(mask.dithering_type.name(), "expr");
+        assert_eq!((mask.brigthness_delta, mask.constrast_delta), (5, 20.0));
+        assert_eq!(mask.color_map.len(), config.color_map.len());
+        assert_eq!(mask.image.width(), 4);
+        let again = ProcessConfig::to_config(ProcessConfig::to_json(&config)).unwrap();
+        assert_eq!(format!("{:?}", config), format!("{:?}", again));
+
+        let config = ProcessConfig::to_config(weighted).unwrap();
+        let importance = config.importance.as_ref().unwrap();
+        assert!(importance.invert);
+        assert_eq!((importance.pattern_size, importance.contrast), (3.0, 1.0));
+        let again = ProcessConfig::to_config(ProcessConfig::to_json(&config)).unwrap();
+        assert_eq!(format!("{:?}", config), format!("{:?}", again));
+
+        let with_dbs = masked.replace(r#""output_scale": 4"#, r#""output_scale": 4, "dbs": true"#);
+        assert!(matches!(ProcessConfig::to_config(with_dbs), Err(DitherError::Config { .. })));
+
+        let missing = masked.replace("ditherpunker_test_mask", "ditherpunker_missing_mask");
+        assert!(ProcessConfig::to_config(missing).is_err());
+    }
+
+    #[test]
+    fn test_config_errors() {
+        let json = r#"{ "brigthness_delta": 0, "constrast_delta": 0, "processing_width": 8, "processing_height": 8, "dithering_type": "bayer_0", "output_scale": 1 }"#;
+        let config = ProcessConfig::to_config(json.to_string()).unwrap();
+
+        match ProcessConfig::to_config(json.replace("bayer_0", "bayer_9")) {
+            Err(DitherError::Config { field, .. }) => assert_eq!(field, "dithering_type"),
+            other => panic!("expected a config error, got {:?}", other.map(|_| ())),
+        }
+        assert!(matches!(ProcessConfig::to_config("{".to_string()), Err(DitherError::Config { .. })));
+        let short_color = json.replace("}", r#", "color_map": ["000", "fff"] }"#);
+        assert!(matches!(ProcessConfig::to_config(short_color), Err(DitherError::Palette(_))));
+        for dtype in ["riemersma", "golden_ratio"] {
+            let seamless = json.replace(r#""bayer_0""#, &format!(r#""{}", "tileable": true"#, dtype));
+            match ProcessConfig::to_config(seamless) {
+                Err(DitherError::Config { field, .. }) => assert_eq!(field, "tileable"),
+                other => panic!("expected a config error, got {:?}", other.map(|_| ())),
+            }
+        }
+
+        for stipple in [r#"{ "min_radius": -1 }"#, r#"{ "min_radius": 2, "max_radius": 1 }"#] {
+            let dots = json.replace("}", &format!(r#", "stipple": {} }}"#, stipple));
+            assert!(matches!(ProcessConfig::to_config(dots), Err(DitherError::Config { .. })), "{}", stipple);
+        }
+
+        // configs built in code are checked before processing
+        let image = image::DynamicImage::new_rgba8(4, 4);
+        let empty = ProcessConfig { color_map: Vec::new(), ..config.clone() };
+        assert!(matches!(crate::run(empty, image.clone()), Err(DitherError::Palette(_))));
+        let flat = ProcessConfig { processing_width: 0, ..config.clone() };
+        assert!(matches!(crate::run(flat, image.clone()), Err(DitherError::Dimensions { .. })));
+        let nothing = image::DynamicImage::new_rgba8(0, 3);
+        assert!(matches!(crate::run(config.clone(), nothing), Err(DitherError::Dimensions { .. })));
+        assert!(crate::run(config, image).is_ok());
     }
 }
-impl Error for ConfigError {}
@@ -0,0 +1,359 @@
+use std::{borrow::Cow, collections::HashMap};
+
+use image::DynamicImage;
+
+use crate::{
+    alpha::AlphaMode,
+    color_palette::{color_map_from_colors, ColorMapElement, DEFAULT_COLOR_MAP},
+    config::ProcessConfig,
+    dithering::{noise::NoiseDistribution, DitheringType},
+    edges::EdgeOptions,
+    error::DitherError,
+    filters::Filter,
+    pixel_util::RGB,
+    process,
+    resize::{FitMode, OutputScale, ResizeFilter},
+    spot::SpotColor,
+    stipple,
+    stipple::StippleOptions,
+    upscale,
+};
+
+// builds the processing pipeline in code instead of a config file, e.g.
+//     Ditherer::new().algorithm(DitheringType::Atkinson).resize(160, 144).scale(4.0).build()?
+// everything not set keeps the defaults of a config file, and without resize every image is
+// dithered at its own size. the mask, the importance map, dbs, the crop and the pad are only
+// read from a config, through from_config
+#[derive(Debug, Clone)]
+pub struct Ditherer {
+    config: ProcessConfig,
+    // the processing box, when given
+    size: Option<(u32, u32)>,
+    // an evenly spaced palette, turned into the color map when building
+    palette: Option<Vec<RGB>>,
+}
+
+impl Default for Ditherer {
+    fn default() -> Self {
+        Ditherer::new()
+    }
+}
+
+impl Ditherer {
+    pub fn new() -> Ditherer {
+        Ditherer {
+            config: ProcessConfig {
+                filters: Vec::new(),
+                auto_tone: None,
+                brigthness_delta: 0,
+                constrast_delta: 0.0,
+                dithering_type: DitheringType::FloydSteinberg,
+                color_map: DEFAULT_COLOR_MAP.to_vec(),
+                processing_width: 1,
+                processing_height: 1,
+                resize_filter: ResizeFilter::default(),
+                fit: FitMode::default(),
+                crop: None,
+                output_scale: OutputScale::uniform(1.0),
+                pad: None,
+                seed: None,
+                noise: NoiseDistribution::default(),
+                hashed_noise: false,
+                tileable: false,
+                dbs: None,
+                stipple: None,
+                edges: None,
+                alpha: None,
+                spot_colors: Vec::new(),
+                mask: None,
+                importance: None,
+            },
+            size: None,
+            palette: None,
+        }
+    }
+
+    // starts from a whole config, e.g. read from a file, the builder methods change it further
+    pub fn from_config(config: &ProcessConfig) -> Ditherer {
+        Ditherer {
+            config: config.clone(),
+            size: Some((config.processing_width, config.processing_height)),
+            palette: None,
+        }
+    }
+
+    pub fn algorithm(mut self, dithering_type: DitheringType) -> Self {
+        self.config.dithering_type = dithering_type;
+        self
+    }
+
+    // colors from the darkest level to the lightest, evenly spaced
+    pub fn palette(mut self, colors: &[RGB]) -> Self {
+        self.palette = Some(colors.to_vec());
+        self
+    }
+
+    // a color map with its own scales and offsets, like in a config file
+    pub fn color_map(mut self, color_map: &[ColorMapElement]) -> Self {
+        self.config.color_map = color_map.to_vec();
+        self.palette = None;
+        self
+    }
+
+    // the processing box, the image is brought to it following the fit mode
+    pub fn resize(mut self, width: u32, height: u32) -> Self {
+        self.size = Some((width, height));
+        self
+    }
+
+    pub fn fit(mut self, fit: FitMode) -> Self {
+        self.config.fit = fit;
+        self
+    }
+
+    pub fn resize_filter(mut self, filter: ResizeFilter) -> Self {
+        self.config.resize_filter = filter;
+        self
+    }
+
+    // output pixels per processing pixel
+    pub fn scale(mut self, scale: f64) -> Self {
+        self.config.output_scale = OutputScale::uniform(scale);
+        self
+    }
+
+    pub fn brightness(mut self, delta: i32) -> Self {
+        self.config.brigthness_delta = delta;
+        self
+    }
+
+    pub fn contrast(mut self, delta: f32) -> Self {
+        self.config.constrast_delta = delta;
+        self
+    }
+
+    // run in the order they are added
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.config.filters.push(filter);
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.config.seed = Some(seed);
+        self
+    }
+
+    pub fn tileable(mut self, tileable: bool) -> Self {
+        self.config.tileable = tileable;
+        self
+    }
+
+    // dots instead of dithering, dither renders them and indices is an error
+    pub fn stipple(mut self, options: StippleOptions) -> Self {
+        self.config.stipple = Some(options);
+        self
+    }
+
+    // outlines drawn over the dithered image
+    pub fn edges(mut self, options: EdgeOptions) -> Self {
+        self.config.edges = Some(options);
+        self
+    }
+
+    // kept in color in the order they are added, the first match wins
+    pub fn spot_color(mut self, spot_color: SpotColor) -> Self {
+        self.config.spot_colors.push(spot_color);
+        self
+    }
+
+    pub fn alpha(mut self, alpha: AlphaMode) -> Self {
+        self.config.alpha = Some(alpha);
+        self
+    }
+
+    // checks the settings once, the pipeline can then dither any number of images
+    pub fn build(&self) -> Result<Pipeline, DitherError> {
+        let mut config = self.config.clone();
+        if let Some(palette) = &self.palette {
+            config.color_map = color_map_from_colors(palette)?;
+        }
+        if let Some((width, height)) = self.size {
+            (config.processing_width, config.processing_height) = (width, height);
+        }
+        config.validate()?;
+
+        Ok(Pipeline {
+            config,
+            sized: self.size.is_some(),
+        })
+    }
+}
+
+// a validated pipeline, reusable across images
+#[derive(Debug, Clone)]
+pub struct Pipeline {
+    config: ProcessConfig,
+    // false when every image is processed at its own size
+    sized: bool,
+}
+
+// the dithered image as indices into a palette, at processing size
+#[derive(Debug, Clone, PartialEq)]
+pub struct Indexed {
+    pub width: u32,
+    pub height: u32,
+    // the colors of the color map in order, followed by any other color in the output (spot
+    // colors, outlines, transparency) in order of appearance
+    pub palette: Vec<RGB>,
+    // one per pixel, in row order
+    pub indices: Vec<usize>,
+}
+
+impl Pipeline {
+    pub fn config(&self) -> &ProcessConfig {
+        &self.config
+    }
+
+    // the dithered image, scaled for the output, or the stippled one with a stipple config
+    pub fn dither(&self, image: &DynamicImage) -> Result<DynamicImage, DitherError> {
+        let config = self.config_for(image);
+        if config.stipple.is_some() {
+            return Ok(stipple(&config, image)?.render(config.output_scale));
+        }
+        let dithered = process(&config, image)?;
+        upscale(
+            &config,
+            &dithered.pixels,
+            dithered.prepared.width(),
+            dithered.prepared.height(),
+        )
+    }
+
+    pub fn indices(&self, image: &DynamicImage) -> Result<Indexed, DitherError> {
+        if self.config.stipple.is_some() {
+            return Err(DitherError::config("stipple", "dots have no palette indices"));
+        }
+        let dithered = process(&self.config_for(image), image)?;
+
+        let mut palette = self.config.color_map.iter().map(|element| element.color).collect::<Vec<RGB>>();
+        // the first index of every color, a repeated color of the map keeps its first place
+        let mut lookup: HashMap<[u64; 4], usize> = HashMap::new();
+        for (index, color) in palette.iter().enumerate() {
+            lookup.entry(color_key(color)).or_insert(index);
+        }
+        let indices = dithered
+            .pixels
+            .iter()
+            .map(|pixel| {
+                *lookup.entry(color_key(pixel)).or_insert_with(|| {
+                    palette.push(*pixel);
+                    palette.len() - 1
+                })
+            })
+            .collect();
+
+        Ok(Indexed {
+            width: dithered.prepared.width(),
+            height: dithered.prepared.height(),
+            palette,
+            indices,
+        })
+    }
+
+    fn config_for(&self, image: &DynamicImage) -> Cow<'_, ProcessConfig> {
+        if self.sized {
+            return Cow::Borrowed(&self.config);
+        }
+        let mut config = self.config.clone();
+        (config.processing_width, config.processing_height) = (image.width(), image.height());
+        Cow::Owned(config)
+    }
+}
+
+// the bits of the channels, adding 0.0 turns -0.0 into 0.0 so equal colors share their key
+fn color_key(color: &RGB) -> [u64; 4] {
+    [color.r, color.g, color.b, color.a].map(|channel| (channel + 0.0).to_bits())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    fn ramp(width: u32, height: u32) -> DynamicImage {
+        RgbaImage::from_fn(width, height, |x, _| {
+            let value = (x * 255 / (width - 1)) as u8;
+            Rgba([value, value, value, 255])
+        })
+        .into()
+    }
+
+    #[test]
+    fn test_build_validates() {
+        let black = RGB::from_u8(0, 0, 0, 255);
+        assert!(matches!(Ditherer::new().palette(&[black]).build(), Err(DitherError::Palette(_))));
+        assert!(matches!(Ditherer::new().scale(0.0).build(), Err(DitherError::Config { .. })));
+        assert!(matches!(Ditherer::new().resize(0, 10).build(), Err(DitherError::Dimensions { .. })));
+        assert!(Ditherer::new().build().is_ok());
+    }
+
+    #[test]
+    fn test_pipeline_is_reusable() {
+        let pipeline = Ditherer::new().algorithm(DitheringType::Bayer1).scale(2.0).build().unwrap();
+        for (width, height) in [(16, 8), (5, 3)] {
+            let dithered = pipeline.dither(&ramp(width, height)).unwrap();
+            assert_eq!((dithered.width(), dithered.height()), (width * 2, height * 2));
+        }
+
+        let boxed = Ditherer::new().resize(8, 8).build().unwrap();
+        let dithered = boxed.dither(&ramp(32, 16)).unwrap();
+        assert_eq!((dithered.width(), dithered.height()), (8, 4));
+    }
+
+    #[test]
+    fn test_indices() {
+        let colors = [RGB::from_u8(0, 0, 0, 255), RGB::from_u8(255, 0, 0, 255), RGB::from_u8(255, 255, 255, 255)];
+        let pipeline = Ditherer::new().algorithm(DitheringType::Atkinson).palette(&colors).build().unwrap();
+        let image = ramp(32, 4);
+
+        let indexed = pipeline.indices(&image).unwrap();
+        assert_eq!(indexed.palette, colors);
+        assert_eq!(indexed.indices.len(), 32 * 4);
+        assert!(indexed.indices.iter().all(|index| *index < colors.len()));
+        // the same pixels as the image
+        let dithered = crate::image_utils::dynimg_to_rgb(&pipeline.dither(&image).unwrap());
+        let from_indices = indexed.indices.iter().map(|index| indexed.palette[*index]).collect::<Vec<RGB>>();
+        assert_eq!(dithered, from_indices);
+    }
+
+    #[test]
+    fn test_stipple_config() {
+        let stipple = StippleOptions { points: 20, ..Default::default() };
+        let pipeline = Ditherer::new().resize(32, 8).scale(2.0).seed(5).stipple(stipple).build().unwrap();
+        let image = ramp(32, 8);
+
+        let stippled = pipeline.dither(&image).unwrap();
+        assert_eq!(stippled, crate::run(pipeline.config().clone(), image.clone()).unwrap());
+        assert_eq!(Ditherer::from_config(pipeline.config()).build().unwrap().dither(&image).unwrap(), stippled);
+        assert_eq!((stippled.width(), stippled.height()), (64, 16));
+        assert!(matches!(pipeline.indices(&image), Err(DitherError::Config { .. })));
+    }
+
+    #[test]
+    fn test_options_reach_the_config() {
+        let red = RGB::from_u8(255, 0, 0, 255);
+        let spot = SpotColor { color: Some(red), ..Default::default() };
+        let edges = EdgeOptions { color: Some(red), ..Default::default() };
+        let pipeline = Ditherer::new()
+            .edges(edges)
+            .spot_color(spot)
+            .alpha(AlphaMode::Dither)
+            .build()
+            .unwrap();
+        let config = pipeline.config();
+        assert_eq!(config.edges.as_ref().map(|edges| edges.color), Some(Some(red)));
+        assert_eq!(config.spot_colors, vec![spot]);
+        assert_eq!(config.alpha, Some(AlphaMode::Dither));
+        assert_eq!(pipeline.dither(&ramp(16, 4)).unwrap().width(), 16);
+    }
+}
@@ -0,0 +1,272 @@
+use crate::{
+    color_palette::ColorMapElement, image_utils::gaussian_kernel, metrics::DEFAULT_HVS_SIGMA,
+    pixel_util::RGB,
+};
+
+// settings of the direct binary search
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DbsOptions {
+    // maximum number of passes over the image
+    pub iterations: u32,
+    // stops once a pass lowers the error by less than this fraction
+    pub convergence: f64,
+    // standard deviation (in processing pixels) of the gaussian model of the eye
+    pub filter_width: f64,
+}
+
+impl Default for DbsOptions {
+    fn default() -> Self {
+        DbsOptions {
+            iterations: 10,
+            convergence: 0.001,
+            filter_width: DEFAULT_HVS_SIGMA,
+        }
+    }
+}
+
+// reported after every pass
+#[derive(Debug, Clone, Copy)]
+pub struct DbsProgress {
+    pub pass: u32,
+    pub iterations: u32,
+    // toggles and swaps kept during the pass
+    pub changes: usize,
+    // perceived error per pixel, the squared difference after the eye filter
+    pub error: f64,
+}
+
+// Direct binary search (Analoui and Allebach): starting from an already dithered image, every pixel
+// tries the neighbouring colors of the palette and swapping its color with each of its 8
+// neighbours, keeping whatever lowers the difference to the reference seen through a gaussian
+// model of the eye. the error is tracked through its correlation with the filter (c_ep) so a trial
+// costs a few lookups, only accepted changes update the area around them.
+// colors are compared by luminance, like the metrics, `reference` being the luminance before dithering
+// an empty color map, or a reference or data not matching the size, leaves the pixels as they are
+pub fn refine(
+    data: &mut [RGB],
+    reference: &[f64],
+    width: u32,
+    height: u32,
+    color_map: &[ColorMapElement],
+    options: &DbsOptions,
+    mut progress: impl FnMut(&DbsProgress),
+) {
+    let (width, height) = (width as usize, height as usize);
+    if color_map.is_empty() || data.len() != width * height || reference.len() != data.len() {
+        return;
+    }
+
+    // palette from the darkest to the lightest color, a toggle moves one step along it
+    let mut palette = color_map.iter().map(|element| element.color).collect::<Vec<RGB>>();
+    palette.sort_by(|a, b| a.grayscale().total_cmp(&b.grayscale()));
+    let levels = palette.iter().map(|color| color.grayscale()).collect::<Vec<f64>>();
+    let mut indexes = data
+        .iter()
+        .map(|pixel| match palette.iter().position(|color| color == pixel) {
+            Some(index) => index,
+            None => nearest(pixel.grayscale(), &levels),
+        })
+        .collect::<Vec<usize>>();
+
+    // autocorrelation of the eye filter, separable like the gaussian itself
+    let autocorrelation = autocorrelation(&gaussian_kernel(options.filter_width.clamp(0.1, width.max(height) as f64)));
+    let radius = (autocorrelation.len() / 2) as isize;
+    let c_pp = |dx: isize, dy: isize| -> f64 {
+        if dx.abs() > radius || dy.abs() > radius {
+            return 0.0;
+        }
+        autocorrelation[(dx + radius) as usize] * autocorrelation[(dy + radius) as usize]
+    };
+    let c_pp_0 = c_pp(0, 0);
+
+    let difference = (0..data.len())
+        .map(|index| levels[indexes[index]] - reference[index])
+        .collect::<Vec<f64>>();
+    let mut c_ep = correlate(&difference, width, height, &autocorrelation);
+    let mut error: f64 = difference.iter().zip(c_ep.iter()).map(|(d, c)| d * c).sum();
+
+    // spreads the change of a pixel by `delta` into c_ep
+    let update = |c_ep: &mut [f64], x: usize, y: usize, delta: f64| {
+        for qy in (y as isize - radius).max(0)..(y as isize + radius + 1).min(height as isize) {
+            for qx in (x as isize - radius).max(0)..(x as isize + radius + 1).min(width as isize) {
+                c_ep[qy as usize * width + qx as usize] += delta * c_pp(qx - x as isize, qy - y as isize);
+            }
+        }
+    };
+
+    for pass in 1..=options.iterations {
+        let error_before = error;
+        let mut changes = 0;
+
+        for y in 0..height {
+            for x in 0..width {
+                let index = y * width + x;
+                let level = levels[indexes[index]];
+
+                // (change of the error, new color of the pixel, swapped neighbour)
+                let mut best: (f64, usize, Option<usize>) = (0.0, indexes[index], None);
+
+                let toggles = [indexes[index].checked_sub(1), Some(indexes[index] + 1)];
+                for candidate in toggles.into_iter().flatten().filter(|c| *c < levels.len()) {
+                    let a0 = levels[candidate] - level;
+                    let delta = a0 * a0 * c_pp_0 + 2.0 * a0 * c_ep[index];
+                    if delta < best.0 {
+                        best = (delta, candidate, None);
+                    }
+                }
+
+                for (dx, dy) in NEIGHBOURS {
+                    let (nx, ny) = (x as isize + dx, y as isize + dy);
+                    if nx < 0 || ny < 0 || nx >= width as isize || ny >= height as isize {
+                        continue;
+                    }
+                    let neighbour = ny as usize * width + nx as usize;
+                    if indexes[neighbour] == indexes[index] {
+                        continue;
+                    }
+                    let a0 = levels[indexes[neighbour]] - level;
+                    let delta = 2.0 * a0 * a0 * (c_pp_0 - c_pp(dx, dy))
+                        + 2.0 * a0 * (c_ep[index] - c_ep[neighbour]);
+                    if delta < best.0 {
+                        best = (delta, indexes[neighbour], Some(neighbour));
+                    }
+                }
+
+                let (delta, candidate, swapped) = best;
+                if candidate == indexes[index] {
+                    continue;
+                }
+
+                let a0 = levels[candidate] - level;
+                if let Some(neighbour) = swapped {
+                    indexes[neighbour] = indexes[index];
+                    update(&mut c_ep, neighbour % width, neighbour / width, -a0);
+                }
+                indexes[index] = candidate;
+                update(&mut c_ep, x, y, a0);
+                error += delta;
+                changes += 1;
+            }
+        }
+
+        progress(&DbsProgress {
+            pass,
+            iterations: options.iterations,
+            changes,
+            error: error.max(0.0) / data.len() as f64,
+        });
+
+        if changes == 0 || (error_before - error) <= options.convergence * error_before {
+            break;
+        }
+    }
+
+    for (pixel, index) in data.iter_mut().zip(indexes) {
+        *pixel = palette[index];
+    }
+}
+
+const NEIGHBOURS: [(isize, isize); 8] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
+fn nearest(value: f64, levels: &[f64]) -> usize {
+    let mut nearest = 0;
+    for (index, level) in levels.iter().enumerate() {
+        if (value - level).abs() < (value - levels[nearest]).abs() {
+            nearest = index;
+        }
+    }
+    nearest
+}
+
+// the kernel correlated with itself, twice as wide
+fn autocorrelation(kernel: &[f64]) -> Vec<f64> {
+    let length = kernel.len() as isize;
+    (-(length - 1)..length)
+        .map(|shift| {
+            (0..length)
+                .filter(|i| i + shift >= 0 && i + shift < length)
+                .map(|i| kernel[i as usize] * kernel[(i + shift) as usize])
+                .sum()
+        })
+        .collect()
+}
+
+// separable correlation with a symmetric kernel, nothing outside of the image
+fn correlate(values: &[f64], width: usize, height: usize, kernel: &[f64]) -> Vec<f64> {
+    let radius = (kernel.len() / 2) as isize;
+    let (w, h) = (width as isize, height as isize);
+
+    let mut horizontal = vec![0.0; values.len()];
+    for y in 0..h {
+        for x in 0..w {
+            horizontal[(y * w + x) as usize] = (-radius..=radius)
+                .filter(|k| x + k >= 0 && x + k < w)
+                .map(|k| values[(y * w + x + k) as usize] * kernel[(k + radius) as usize])
+                .sum();
+        }
+    }
+
+    let mut correlated = vec![0.0; values.len()];
+    for y in 0..h {
+        for x in 0..w {
+            correlated[(y * w + x) as usize] = (-radius..=radius)
+                .filter(|k| y + k >= 0 && y + k < h)
+                .map(|k| horizontal[((y + k) * w + x) as usize] * kernel[(k + radius) as usize])
+                .sum();
+        }
+    }
+    correlated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        color_palette::DEFAULT_COLOR_MAP,
+        dithering::{DitherOptions, DitheringType},
+        image_utils::luminance,
+        metrics::hvs_mse,
+    };
+
+    #[test]
+    fn test_refine_lowers_the_perceived_error() {
+        let (width, height) = (48, 48);
+        let input = (0..width * height)
+            .map(|index| {
+                let value = (index % width) as f64 / (width - 1) as f64;
+                RGB { r: value, g: value, b: value, a: 1.0 }
+            })
+            .collect::<Vec<RGB>>();
+        let mut data = input.clone();
+        let options = DitherOptions { seed: Some(5), ..Default::default() };
+        DitheringType::Rand.dither(&mut data, width, height, &DEFAULT_COLOR_MAP, &options);
+
+        let before = hvs_mse(&input, &data, width, height, DEFAULT_HVS_SIGMA);
+        let mut passes = Vec::new();
+        refine(
+            &mut data,
+            &luminance(&input),
+            width,
+            height,
+            &DEFAULT_COLOR_MAP,
+            &DbsOptions::default(),
+            |progress| passes.push(*progress),
+        );
+        let after = hvs_mse(&input, &data, width, height, DEFAULT_HVS_SIGMA);
+
+        assert!(after < before / 2.0, "{} -> {}", before, after);
+        assert!(!passes.is_empty());
+        // every pass makes things better
+        assert!(passes.windows(2).all(|pair| pair[1].error <= pair[0].error));
+        assert!(data.iter().all(|pixel| DEFAULT_COLOR_MAP.iter().any(|e| e.color == *pixel)));
+    }
+}
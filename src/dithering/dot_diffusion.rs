@@ -0,0 +1,156 @@
+use crate::{
+    color_palette::ColorMapElement,
+    dithering::error_diffusion::{nearest_level, normalize_color_map},
+    pixel_util::RGB,
+};
+
+// Knuth, "Digital Halftones by Dot Diffusion" (1987)
+const KNUTH: [usize; 64] = [
+    34, 48, 40, 32, 29, 15, 23, 31,
+    42, 58, 56, 53, 21, 5, 7, 10,
+    50, 62, 61, 45, 13, 1, 2, 18,
+    38, 46, 54, 37, 25, 17, 9, 26,
+    28, 14, 22, 30, 35, 49, 41, 33,
+    20, 4, 6, 11, 43, 59, 57, 52,
+    12, 0, 3, 19, 51, 63, 60, 44,
+    24, 16, 8, 27, 39, 47, 55, 36,
+];
+
+// weight of the error given to the orthogonal and the diagonal neighbours
+const ORTHOGONAL_WEIGHT: f64 = 2.0;
+const DIAGONAL_WEIGHT: f64 = 1.0;
+
+// the order pixels are processed in, tiled over the image
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassMatrix {
+    width: usize,
+    height: usize,
+    classes: Vec<usize>,
+}
+
+impl ClassMatrix {
+    // rows of the same length, using every class from 0 to (width * height - 1) once
+    pub fn new(rows: &[Vec<usize>]) -> Option<ClassMatrix> {
+        let height = rows.len();
+        let width = rows.first()?.len();
+        if width == 0 || rows.iter().any(|row| row.len() != width) {
+            return None;
+        }
+
+        let classes = rows.concat();
+        let mut used = vec![false; classes.len()];
+        for class in &classes {
+            if *class >= used.len() || used[*class] {
+                return None;
+            }
+            used[*class] = true;
+        }
+
+        Some(ClassMatrix {
+            width,
+            height,
+            classes,
+        })
+    }
+
+    pub fn knuth() -> ClassMatrix {
+        ClassMatrix {
+            width: 8,
+            height: 8,
+            classes: KNUTH.to_vec(),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn rows(&self) -> Vec<Vec<usize>> {
+        self.classes.chunks(self.width).map(|row| row.to_vec()).collect()
+    }
+
+    fn class(&self, x: usize, y: usize) -> usize {
+        self.classes[y % self.height * self.width + x % self.width]
+    }
+}
+
+// Knuth's dot diffusion: the class matrix is tiled over the image and pixels are quantized class
+// after class, each one spreading its error only to the neighbours of a higher class, which are
+// still to be processed. pixels of one class never pass error to each other so a class could be
+// done in parallel, and no error travels further than the tile, keeping edges sharper than with
+// error diffusion. the pixels of the highest class (barons) have nobody left and drop their error.
+// tileable, the neighbours wrap around the borders
+pub fn dither(
+    data: &mut [RGB],
+    width: u32,
+    height: u32,
+    color_map: &[ColorMapElement],
+    class_matrix: &ClassMatrix,
+    tileable: bool,
+) {
+    let mut n_color_map = color_map.to_vec();
+    normalize_color_map(&mut n_color_map);
+
+    let (width, height) = (width as usize, height as usize);
+    let mut values = data.iter().map(|pixel| pixel.grayscale()).collect::<Vec<f64>>();
+
+    // every pixel, grouped by class
+    let mut order: Vec<Vec<usize>> = vec![Vec::new(); class_matrix.classes.len()];
+    for index in 0..width * height {
+        order[class_matrix.class(index % width, index / width)].push(index);
+    }
+
+    for index in order.into_iter().flatten() {
+        let (x, y) = (index % width, index / width);
+        let class = class_matrix.class(x, y);
+
+        let element = n_color_map[nearest_level(values[index], &n_color_map)];
+        let error = values[index] - element.scale;
+        data[index] = element.color;
+
+        let mut neighbours: Vec<(usize, f64)> = Vec::with_capacity(8);
+        for dy in -1..=1isize {
+            for dx in -1..=1isize {
+                let (mut nx, mut ny) = (x as isize + dx, y as isize + dy);
+                if tileable {
+                    (nx, ny) = (nx.rem_euclid(width as isize), ny.rem_euclid(height as isize));
+                }
+                if (dx == 0 && dy == 0) || nx < 0 || ny < 0 || nx >= width as isize || ny >= height as isize {
+                    continue;
+                }
+                let (nx, ny) = (nx as usize, ny as usize);
+                if class_matrix.class(nx, ny) > class {
+                    let weight = if dx == 0 || dy == 0 { ORTHOGONAL_WEIGHT } else { DIAGONAL_WEIGHT };
+                    neighbours.push((ny * width + nx, weight));
+                }
+            }
+        }
+
+        let total: f64 = neighbours.iter().map(|(_, weight)| weight).sum();
+        for (neighbour, weight) in neighbours {
+            values[neighbour] += error * weight / total;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_class_matrix() {
+        let knuth = ClassMatrix::knuth();
+        assert_eq!(ClassMatrix::new(&knuth.rows()), Some(knuth));
+
+        assert!(ClassMatrix::new(&[vec![0, 1], vec![2, 3]]).is_some());
+        // a class used twice, a missing class, ragged rows
+        assert!(ClassMatrix::new(&[vec![0, 1], vec![1, 3]]).is_none());
+        assert!(ClassMatrix::new(&[vec![0, 1], vec![2, 4]]).is_none());
+        assert!(ClassMatrix::new(&[vec![0, 1, 2], vec![3]]).is_none());
+        assert!(ClassMatrix::new(&[]).is_none());
+    }
+}
@@ -1,27 +1,47 @@
-use crate::{color_palette::ColorMapElement, pixel_util::RGB};
+use std::borrow::Cow;
+
+use crate::{
+    color_palette::ColorMapElement,
+    dithering::DitherOptions,
+    pixel_util::RGB,
+};
 use matrices::{
     ATKINSON, ATKINSON_SIZE, FLOYD_STEINBERG, FLOYD_STEINBERG_SIZE, JARVIS_JUDICE_NINKE,
-    JARVIS_JUDICE_NINKE_SIZE,
+    JARVIS_JUDICE_NINKE_SIZE, OSTROMOUKHOV,
 };
 
 mod matrices;
 
+// the variable coefficient kernels spread the error to the right, down-left and down neighbours
+const VARIABLE_SIZE: [usize; 2] = [3, 2];
+const VARIABLE_ORIGIN: usize = 1;
+// how far the threshold of zhou-fang moves around mid-grey, in steps between two levels
+const MODULATION_STRENGTH: f64 = 0.5;
+// scans of a tileable image, the first one has no error coming over the top and left borders
+const TILEABLE_PASSES: usize = 3;
+
 #[derive(Debug, Clone, Copy)]
 pub enum ErrorDiffusionType {
     FloydSteinberg,
     JarvisJudiceNinke,
     Atkinson,
+    // the weights depend on the input level
+    Ostromoukhov,
+    // ostromoukhov's weights with a random threshold modulation, breaking up the patterns left
+    // around some levels
+    ZhouFang,
 }
 
 impl ErrorDiffusionType {
     pub fn dither(
         self,
-        data: &mut Vec<RGB>,
+        data: &mut [RGB],
         width: u32,
         height: u32,
-        color_map: &Vec<ColorMapElement>,
+        color_map: &[ColorMapElement],
+        options: &DitherOptions,
     ) {
-        let mut n_color_map = color_map.clone();
+        let mut n_color_map = color_map.to_vec();
         normalize_color_map(&mut n_color_map);
 
         match self {
@@ -32,6 +52,7 @@ impl ErrorDiffusionType {
                 width,
                 height,
                 &n_color_map,
+                options.tileable,
             ),
             ErrorDiffusionType::JarvisJudiceNinke => ErrorDiffusionType::dither_helper(
                 JARVIS_JUDICE_NINKE.to_vec(),
@@ -40,6 +61,7 @@ impl ErrorDiffusionType {
                 width,
                 height,
                 &n_color_map,
+                options.tileable,
             ),
             ErrorDiffusionType::FloydSteinberg => ErrorDiffusionType::dither_helper(
                 FLOYD_STEINBERG.to_vec(),
@@ -48,37 +70,136 @@ impl ErrorDiffusionType {
                 width,
                 height,
                 &n_color_map,
+                options.tileable,
+            ),
+            ErrorDiffusionType::Ostromoukhov => ErrorDiffusionType::variable_dither_helper(
+                VARIABLE_SIZE,
+                VARIABLE_ORIGIN,
+                true,
+                options.tileable,
+                data,
+                width,
+                height,
+                &n_color_map,
+                |original| Cow::Owned(ostromoukhov_weights(original, &n_color_map)),
+                |_, _, _| 0.0,
             ),
+            ErrorDiffusionType::ZhouFang => {
+                let mut noise = options.noise_source();
+                ErrorDiffusionType::variable_dither_helper(
+                    VARIABLE_SIZE,
+                    VARIABLE_ORIGIN,
+                    true,
+                    options.tileable,
+                    data,
+                    width,
+                    height,
+                    &n_color_map,
+                    |original| Cow::Owned(ostromoukhov_weights(original, &n_color_map)),
+                    |x, y, original| {
+                        // strongest in the mid-tones, none on the levels themselves
+                        let (position, step) = position_between_levels(original, &n_color_map);
+                        let strength = MODULATION_STRENGTH * (std::f64::consts::PI * position).sin();
+                        (noise.next(x, y) - 0.5) * strength * step
+                    },
+                )
+            }
         }
     }
 
     fn dither_helper(
         matrix: Vec<f64>,
         matrix_dimenisons: [usize; 2],
-        data: &mut Vec<RGB>,
+        data: &mut [RGB],
         width: u32,
-        _height: u32,
-        color_map: &Vec<ColorMapElement>,
+        height: u32,
+        color_map: &[ColorMapElement],
+        tileable: bool,
     ) {
-        /*
-        prepare utils and variables
-        */
         let mut factor = matrix.clone();
         let origin = factor.iter().position(|x| *x == -1.0).unwrap();
-        let offsets = calculate_offset_matrix(matrix_dimenisons, width, origin);
         factor[origin] = 0.0;
 
-        let mut index_data: usize = 0;
-        while index_data < data.len()  {
+        ErrorDiffusionType::variable_dither_helper(
+            matrix_dimenisons,
+            origin,
+            false,
+            tileable,
+            data,
+            width,
+            height,
+            color_map,
+            |_| Cow::Borrowed(&factor),
+            |_, _, _| 0.0,
+        );
+    }
+
+    // error diffusion with a kernel picked for every pixel out of its original value, the weights
+    // are laid out like the matrices with the pixel at `origin`. `modulation` (x, y, original value)
+    // moves the value before it's matched to a level, without changing the error that is spread.
+    // serpentine scanning goes right to left on odd rows, with the kernel mirrored.
+    // tileable, the image is a torus: see toroidal_dither_helper
+    #[allow(clippy::too_many_arguments)]
+    fn variable_dither_helper<'a>(
+        matrix_dimenisons: [usize; 2],
+        origin: usize,
+        serpentine: bool,
+        tileable: bool,
+        data: &mut [RGB],
+        width: u32,
+        height: u32,
+        color_map: &[ColorMapElement],
+        mut kernel: impl FnMut(f64) -> Cow<'a, [f64]>,
+        mut modulation: impl FnMut(usize, usize, f64) -> f64,
+    ) {
+        if tileable {
+            return ErrorDiffusionType::toroidal_dither_helper(
+                matrix_dimenisons,
+                origin,
+                serpentine,
+                data,
+                width,
+                height,
+                color_map,
+                kernel,
+                modulation,
+            );
+        }
+
+        /*
+        prepare utils and variables
+        */
+        let width = width as usize;
+        let offsets = calculate_offset_matrix(matrix_dimenisons, width as u32, origin);
+        let mirrored_offsets = offsets
+            .iter()
+            .enumerate()
+            .map(|(index, offset)| {
+                let row_offset = (width * (index / matrix_dimenisons[0])) as isize;
+                2 * row_offset - offset
+            })
+            .collect::<Vec<isize>>();
+        let originals = data.iter().map(|pixel| pixel.grayscale()).collect::<Vec<f64>>();
+
+        let mut step: usize = 0;
+        while step < data.len() {
+            let y = step / width;
+            let reversed = serpentine && y % 2 == 1;
+            let x = if reversed { width - 1 - step % width } else { step % width };
+            let index_data = y * width + x;
 
             /*
             give the pixel a color and calculate the difference
             */
-            let error: f64 = discrete_and_calculate_error(&mut data[index_data], color_map);
+            let original = originals[index_data];
+            let shift = modulation(x, y, original);
+            let error: f64 = discrete_and_calculate_error(&mut data[index_data], shift, color_map);
 
             /*
             distribute the difference to nearby pixels
             */
+            let factor = kernel(original);
+            let offsets = if reversed { &mirrored_offsets } else { &offsets };
             let mut index_matrix: usize = 0;
             while index_matrix < factor.len() {
                 let index = index_data as isize + offsets[index_matrix];
@@ -93,12 +214,100 @@ impl ErrorDiffusionType {
                 index_matrix += 1;
             }
 
-            index_data += 1;
+            step += 1;
         }
     }
+
+    // error diffusion over the image wrapped on a torus, the error leaving a border comes back
+    // on the opposite one. the error wrapped from the bottom rows and the right end of the rows
+    // reaches pixels that are already done, so the image is scanned TILEABLE_PASSES times, every
+    // pass starting with the error left by the previous one, and only the last pass is kept
+    #[allow(clippy::too_many_arguments)]
+    fn toroidal_dither_helper<'a>(
+        matrix_dimenisons: [usize; 2],
+        origin: usize,
+        serpentine: bool,
+        data: &mut [RGB],
+        width: u32,
+        height: u32,
+        color_map: &[ColorMapElement],
+        mut kernel: impl FnMut(f64) -> Cow<'a, [f64]>,
+        mut modulation: impl FnMut(usize, usize, f64) -> f64,
+    ) {
+        let (width, height) = (width as usize, height as usize);
+        // (dx, dy) of every weight of the kernel
+        let offsets = (0..matrix_dimenisons[0] * matrix_dimenisons[1])
+            .map(|index| {
+                (
+                    (index % matrix_dimenisons[0]) as isize - origin as isize,
+                    (index / matrix_dimenisons[0]) as isize,
+                )
+            })
+            .collect::<Vec<(isize, isize)>>();
+        let pixels = data.to_vec();
+        let mut errors = vec![0.0; data.len()];
+
+        for _ in 0..TILEABLE_PASSES {
+            for y in 0..height {
+                let reversed = serpentine && y % 2 == 1;
+                for step in 0..width {
+                    let x = if reversed { width - 1 - step } else { step };
+                    let index = y * width + x;
+
+                    let original = pixels[index].grayscale();
+                    let mut pixel = pixels[index];
+                    pixel.add_luminosity(errors[index]);
+                    errors[index] = 0.0;
+                    let shift = modulation(x, y, original);
+                    let error = discrete_and_calculate_error(&mut pixel, shift, color_map);
+                    data[index] = pixel;
+
+                    let factor = kernel(original);
+                    for (weight, (dx, dy)) in factor.iter().zip(&offsets) {
+                        let dx = if reversed { -dx } else { *dx };
+                        let nx = (x as isize + dx).rem_euclid(width as isize) as usize;
+                        let ny = (y as isize + dy).rem_euclid(height as isize) as usize;
+                        errors[ny * width + nx] += error * weight;
+                    }
+                }
+            }
+        }
+    }
+}
+
+// ostromoukhov's weights for the value, laid out in a VARIABLE_SIZE matrix. with more than two
+// colors the table is indexed by the position of the value between the two levels around it
+fn ostromoukhov_weights(value: f64, color_map: &[ColorMapElement]) -> Vec<f64> {
+    let (position, _) = position_between_levels(value, color_map);
+    let level = (position * 255.0).round() as usize;
+    let [right, down_left, down, sum] = OSTROMOUKHOV[level.min(255 - level)];
+    let sum = sum as f64;
+
+    vec![0.0, 0.0, right as f64 / sum, down_left as f64 / sum, down as f64 / sum, 0.0]
 }
 
-fn normalize_color_map(color_map: &mut Vec<ColorMapElement>) {
+// where the value sits between the two levels of a normalized color map around it, from 0.0 to
+// 1.0, along with the distance between these levels
+fn position_between_levels(value: f64, color_map: &[ColorMapElement]) -> (f64, f64) {
+    if color_map.len() < 2 {
+        return (0.0, 0.0);
+    }
+    let upper = color_map
+        .iter()
+        .position(|element| element.scale > value)
+        .unwrap_or(color_map.len() - 1)
+        .max(1);
+    let low = color_map[upper - 1].scale;
+    let step = color_map[upper].scale - low;
+    if step <= 0.0 {
+        return (0.0, 0.0);
+    }
+
+    (((value - low) / step).clamp(0.0, 1.0), step)
+}
+
+// turns the scales of the color map into the luminance level of each color, from 0.0 to 1.0
+pub(super) fn normalize_color_map(color_map: &mut [ColorMapElement]) {
     let sum = color_map[1..]
         .iter()
         .map(|x| x.scale)
@@ -118,12 +327,13 @@ fn normalize_color_map(color_map: &mut Vec<ColorMapElement>) {
     color_map[0].scale = 0.0;
 }
 
-fn discrete_and_calculate_error(pixel: &mut RGB, color_map: &Vec<ColorMapElement>) -> f64 {
+// index of the color whose level is the closest to the value, in a normalized color map
+pub(super) fn nearest_level(value: f64, color_map: &[ColorMapElement]) -> usize {
     let mut index_map = 0;
     let mut min_index = 0;
     let mut min_diff = f64::MAX;
     while index_map < color_map.len() {
-        let diff = (pixel.grayscale() - color_map[index_map].scale).abs();
+        let diff = (value - color_map[index_map].scale).abs();
         if diff < min_diff {
             min_index = index_map;
             min_diff = diff;
@@ -131,11 +341,14 @@ fn discrete_and_calculate_error(pixel: &mut RGB, color_map: &Vec<ColorMapElement
 
         index_map += 1;
     }
+    min_index
+}
 
-    let last_element = color_map[min_index];
+fn discrete_and_calculate_error(pixel: &mut RGB, shift: f64, color_map: &[ColorMapElement]) -> f64 {
+    let last_element = color_map[nearest_level(pixel.grayscale() + shift, color_map)];
     let error = pixel.grayscale() - last_element.scale ;
     (*pixel) = last_element.color;
-    return error;
+    error
 }
 
 fn calculate_offset_matrix(matrix_dimenisons: [usize; 2], width: u32, origin: usize) -> Vec<isize> {
@@ -152,7 +365,7 @@ fn calculate_offset_matrix(matrix_dimenisons: [usize; 2], width: u32, origin: us
         index_i += 1;
     }
 
-    return offsets;
+    offsets
 }
 
 
@@ -164,7 +377,7 @@ mod tests {
     #[test]
     fn test_calculate_offset_matrix() {
 
-        let error_diffusion_matrix = vec![0.20, -1.0, 0.15, 0.10, 0.10, 0.20, 0.20, 0.05];
+        let error_diffusion_matrix = [0.20, -1.0, 0.15, 0.10, 0.10, 0.20, 0.20, 0.05];
         let origin_index = error_diffusion_matrix.iter().position(|x| *x == -1.0).unwrap();
 
         assert_eq!(origin_index, 1);
@@ -176,7 +389,7 @@ mod tests {
     #[test]
     fn test_calculate_offset_matrix_2() {
 
-        let error_diffusion_matrix = vec![-1.0, 0.0, 0.15, 0.10, 0.10, 0.20, 0.20, 0.05];
+        let error_diffusion_matrix = [-1.0, 0.0, 0.15, 0.10, 0.10, 0.20, 0.20, 0.05];
         let origin_index = error_diffusion_matrix.iter().position(|x| *x == -1.0).unwrap();
 
         assert_eq!(origin_index, 0);
@@ -202,7 +415,62 @@ mod tests {
 
         normalize_color_map(&mut color_map);
 
-        assert_eq!(color_map[0].scale, 0.5);
+        assert_eq!(color_map[0].scale, 0.0);
         assert_eq!(color_map[1].scale, 1.0);
     }
+
+    #[test]
+    fn test_ostromoukhov_table() {
+        for [right, down_left, down, sum] in OSTROMOUKHOV {
+            assert_eq!(right + down_left + down, sum);
+        }
+
+        // the table is mirrored around mid-grey
+        let color_map = crate::color_palette::DEFAULT_COLOR_MAP.to_vec();
+        let mut n_color_map = color_map.clone();
+        normalize_color_map(&mut n_color_map);
+        assert_eq!(
+            ostromoukhov_weights(10.0 / 255.0, &n_color_map),
+            ostromoukhov_weights(245.0 / 255.0, &n_color_map)
+        );
+    }
+
+    #[test]
+    fn test_variable_kernels_keep_the_average() {
+        let color_map = crate::color_palette::DEFAULT_COLOR_MAP.to_vec();
+        let options = DitherOptions { seed: Some(3), ..Default::default() };
+
+        for dtype in [ErrorDiffusionType::Ostromoukhov, ErrorDiffusionType::ZhouFang] {
+            for grey in [0.1, 0.33, 0.5, 0.8] {
+                let pixel = RGB { r: grey, g: grey, b: grey, a: 1.0 };
+                let mut data = vec![pixel; 64 * 64];
+                dtype.dither(&mut data, 64, 64, &color_map, &options);
+
+                let mean = data.iter().map(|pixel| pixel.grayscale()).sum::<f64>() / data.len() as f64;
+                assert!((mean - grey).abs() < 0.02, "{:?} at {}: {}", dtype, grey, mean);
+            }
+        }
+    }
+
+    #[test]
+    fn test_tileable_error_wraps() {
+        let color_map = crate::color_palette::DEFAULT_COLOR_MAP.to_vec();
+        let options = DitherOptions { tileable: true, ..Default::default() };
+        let (width, height) = (23, 17);
+
+        for dtype in [ErrorDiffusionType::FloydSteinberg, ErrorDiffusionType::Ostromoukhov] {
+            let grey = 0.3;
+            let mut data = vec![RGB { r: grey, g: grey, b: grey, a: 1.0 }; width * height];
+            dtype.dither(&mut data, width as u32, height as u32, &color_map, &options);
+
+            // no error is lost at the borders, the first row and column are as dense as the rest
+            let mean = |pixels: &mut dyn Iterator<Item = &RGB>| {
+                let values = pixels.map(|pixel| pixel.grayscale()).collect::<Vec<f64>>();
+                values.iter().sum::<f64>() / values.len() as f64
+            };
+            assert!((mean(&mut data.iter()) - grey).abs() < 0.01, "{:?}", dtype);
+            assert!((mean(&mut data[..width].iter()) - grey).abs() < 0.1, "{:?}", dtype);
+            assert!((mean(&mut data.iter().step_by(width)) - grey).abs() < 0.1, "{:?}", dtype);
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,525 @@
+use std::{error::Error, f64::consts, fmt::Display};
+
+// a threshold written as a formula, e.g. "fract(sin(x*12.9898 + y*78.233) * 43758.5453)"
+//
+// variables: x, y, width, height, luminance (of the pixel, in [0.0, 1.0]), pi, e
+// operators: + - * / % (always positive) ^ (power) < > <= >= (1.0 when true, 0.0 otherwise)
+// functions: sin cos tan asin acos atan atan2 sqrt abs sign floor ceil round fract exp ln log2
+//            pow min max mod clamp step mix length
+#[derive(Debug, Clone)]
+pub struct ThresholdExpr {
+    source: String,
+    root: Node,
+}
+
+// the values the variables of an expression take for one pixel
+#[derive(Debug, Clone, Copy)]
+pub struct Variables {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+    pub luminance: f64,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Number(f64),
+    Variable(Variable),
+    Negate(Box<Node>),
+    Binary(Operator, Box<Node>, Box<Node>),
+    Call(Function, Vec<Node>),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Variable {
+    X,
+    Y,
+    Width,
+    Height,
+    Luminance,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Operator {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Pow,
+    Less,
+    Greater,
+    LessEqual,
+    GreaterEqual,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Function {
+    Sin,
+    Cos,
+    Tan,
+    Asin,
+    Acos,
+    Atan,
+    Atan2,
+    Sqrt,
+    Abs,
+    Sign,
+    Floor,
+    Ceil,
+    Round,
+    Fract,
+    Exp,
+    Ln,
+    Log2,
+    Pow,
+    Min,
+    Max,
+    Mod,
+    Clamp,
+    Step,
+    Mix,
+    Length,
+}
+
+impl Function {
+    fn from_name(name: &str) -> Option<(Function, usize)> {
+        let function = match name {
+            "sin" => (Function::Sin, 1),
+            "cos" => (Function::Cos, 1),
+            "tan" => (Function::Tan, 1),
+            "asin" => (Function::Asin, 1),
+            "acos" => (Function::Acos, 1),
+            "atan" => (Function::Atan, 1),
+            "atan2" => (Function::Atan2, 2),
+            "sqrt" => (Function::Sqrt, 1),
+            "abs" => (Function::Abs, 1),
+            "sign" => (Function::Sign, 1),
+            "floor" => (Function::Floor, 1),
+            "ceil" => (Function::Ceil, 1),
+            "round" => (Function::Round, 1),
+            "fract" => (Function::Fract, 1),
+            "exp" => (Function::Exp, 1),
+            "ln" => (Function::Ln, 1),
+            "log2" => (Function::Log2, 1),
+            "pow" => (Function::Pow, 2),
+            "min" => (Function::Min, 2),
+            "max" => (Function::Max, 2),
+            "mod" => (Function::Mod, 2),
+            "clamp" => (Function::Clamp, 3),
+            "step" => (Function::Step, 2),
+            "mix" => (Function::Mix, 3),
+            "length" => (Function::Length, 2),
+            _ => return None,
+        };
+        Some(function)
+    }
+
+    fn apply(self, args: &[f64]) -> f64 {
+        match self {
+            Function::Sin => args[0].sin(),
+            Function::Cos => args[0].cos(),
+            Function::Tan => args[0].tan(),
+            Function::Asin => args[0].asin(),
+            Function::Acos => args[0].acos(),
+            Function::Atan => args[0].atan(),
+            Function::Atan2 => args[0].atan2(args[1]),
+            Function::Sqrt => args[0].sqrt(),
+            Function::Abs => args[0].abs(),
+            Function::Sign => {
+                if args[0] == 0.0 {
+                    0.0
+                } else {
+                    args[0].signum()
+                }
+            }
+            Function::Floor => args[0].floor(),
+            Function::Ceil => args[0].ceil(),
+            Function::Round => args[0].round(),
+            // like GLSL, always positive
+            Function::Fract => args[0] - args[0].floor(),
+            Function::Exp => args[0].exp(),
+            Function::Ln => args[0].ln(),
+            Function::Log2 => args[0].log2(),
+            Function::Pow => args[0].powf(args[1]),
+            Function::Min => args[0].min(args[1]),
+            Function::Max => args[0].max(args[1]),
+            Function::Mod => args[0].rem_euclid(args[1]),
+            Function::Clamp => args[0].max(args[1]).min(args[2]),
+            Function::Step => {
+                if args[1] < args[0] {
+                    0.0
+                } else {
+                    1.0
+                }
+            }
+            Function::Mix => args[0] + (args[1] - args[0]) * args[2],
+            Function::Length => args[0].hypot(args[1]),
+        }
+    }
+}
+
+// parentheses, signs, powers and function calls nested deeper than this are refused before
+// the recursion of the parser runs out of stack
+const MAX_DEPTH: usize = 64;
+// also bounds the depth of the tree built by long chains of operators, e.g. 1+1+1+...
+const MAX_LENGTH: usize = 4096;
+
+impl ThresholdExpr {
+    pub fn parse(source: &str) -> Result<ThresholdExpr, ExpressionError> {
+        let mut parser = Parser {
+            chars: source.chars().collect(),
+            position: 0,
+            depth: 0,
+        };
+        if parser.chars.len() > MAX_LENGTH {
+            return Err(ExpressionError {
+                msg: format!("longer than {} characters", MAX_LENGTH),
+                position: MAX_LENGTH,
+            });
+        }
+        let root = parser.comparison()?;
+        parser.skip_whitespace();
+        if parser.position < parser.chars.len() {
+            return Err(parser.error("unexpected character"));
+        }
+
+        Ok(ThresholdExpr {
+            source: source.to_string(),
+            root,
+        })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn evaluate(&self, variables: &Variables) -> f64 {
+        evaluate(&self.root, variables)
+    }
+}
+
+fn evaluate(node: &Node, variables: &Variables) -> f64 {
+    match node {
+        Node::Number(value) => *value,
+        Node::Variable(variable) => match variable {
+            Variable::X => variables.x,
+            Variable::Y => variables.y,
+            Variable::Width => variables.width,
+            Variable::Height => variables.height,
+            Variable::Luminance => variables.luminance,
+        },
+        Node::Negate(node) => -evaluate(node, variables),
+        Node::Binary(operator, left, right) => {
+            let a = evaluate(left, variables);
+            let b = evaluate(right, variables);
+            let truth = |condition: bool| if condition { 1.0 } else { 0.0 };
+            match operator {
+                Operator::Add => a + b,
+                Operator::Sub => a - b,
+                Operator::Mul => a * b,
+                Operator::Div => a / b,
+                Operator::Mod => a.rem_euclid(b),
+                Operator::Pow => a.powf(b),
+                Operator::Less => truth(a < b),
+                Operator::Greater => truth(a > b),
+                Operator::LessEqual => truth(a <= b),
+                Operator::GreaterEqual => truth(a >= b),
+            }
+        }
+        Node::Call(function, args) => {
+            let args = args
+                .iter()
+                .map(|arg| evaluate(arg, variables))
+                .collect::<Vec<f64>>();
+            function.apply(&args)
+        }
+    }
+}
+
+// recursive descent, from the loosest binding operators to the tightest
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+    // number of unary calls in progress, every nesting goes through one
+    depth: usize,
+}
+
+impl Parser {
+    fn error(&self, msg: &str) -> ExpressionError {
+        ExpressionError {
+            msg: String::from(msg),
+            position: self.position,
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.position < self.chars.len() && self.chars[self.position].is_whitespace() {
+            self.position += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.chars.get(self.position).copied()
+    }
+
+    fn consume(&mut self, expected: &str) -> bool {
+        self.skip_whitespace();
+        let end = self.position + expected.chars().count();
+        if end <= self.chars.len() && self.chars[self.position..end].iter().copied().eq(expected.chars()) {
+            self.position = end;
+            return true;
+        }
+        false
+    }
+
+    fn comparison(&mut self) -> Result<Node, ExpressionError> {
+        let left = self.additive()?;
+        // two character operators first so "<=" isn't read as "<"
+        let operator = if self.consume("<=") {
+            Operator::LessEqual
+        } else if self.consume(">=") {
+            Operator::GreaterEqual
+        } else if self.consume("<") {
+            Operator::Less
+        } else if self.consume(">") {
+            Operator::Greater
+        } else {
+            return Ok(left);
+        };
+        let right = self.additive()?;
+        Ok(Node::Binary(operator, Box::new(left), Box::new(right)))
+    }
+
+    fn additive(&mut self) -> Result<Node, ExpressionError> {
+        let mut node = self.term()?;
+        loop {
+            let operator = match self.peek() {
+                Some('+') => Operator::Add,
+                Some('-') => Operator::Sub,
+                _ => return Ok(node),
+            };
+            self.position += 1;
+            node = Node::Binary(operator, Box::new(node), Box::new(self.term()?));
+        }
+    }
+
+    fn term(&mut self) -> Result<Node, ExpressionError> {
+        let mut node = self.unary()?;
+        loop {
+            let operator = match self.peek() {
+                Some('*') => Operator::Mul,
+                Some('/') => Operator::Div,
+                Some('%') => Operator::Mod,
+                _ => return Ok(node),
+            };
+            self.position += 1;
+            node = Node::Binary(operator, Box::new(node), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Node, ExpressionError> {
+        if self.depth >= MAX_DEPTH {
+            return Err(self.error("expression nested too deeply"));
+        }
+        self.depth += 1;
+        let node = self.signed();
+        self.depth -= 1;
+        node
+    }
+
+    fn signed(&mut self) -> Result<Node, ExpressionError> {
+        if self.consume("-") {
+            return Ok(Node::Negate(Box::new(self.unary()?)));
+        }
+        if self.consume("+") {
+            return self.unary();
+        }
+        self.power()
+    }
+
+    // right associative, and binds tighter than a leading minus: -x^2 is -(x^2)
+    fn power(&mut self) -> Result<Node, ExpressionError> {
+        let base = self.primary()?;
+        if self.consume("^") {
+            let exponent = self.unary()?;
+            return Ok(Node::Binary(Operator::Pow, Box::new(base), Box::new(exponent)));
+        }
+        Ok(base)
+    }
+
+    fn primary(&mut self) -> Result<Node, ExpressionError> {
+        match self.peek() {
+            Some('(') => {
+                self.position += 1;
+                let node = self.comparison()?;
+                if !self.consume(")") {
+                    return Err(self.error("expected ')'"));
+                }
+                Ok(node)
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => self.number(),
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => self.identifier(),
+            Some(_) => Err(self.error("unexpected character")),
+            None => Err(self.error("unexpected end of expression")),
+        }
+    }
+
+    fn number(&mut self) -> Result<Node, ExpressionError> {
+        let start = self.position;
+        while self.position < self.chars.len()
+            && (self.chars[self.position].is_ascii_digit() || self.chars[self.position] == '.')
+        {
+            self.position += 1;
+        }
+        // exponent, e.g. 1e-3
+        if self.position < self.chars.len() && matches!(self.chars[self.position], 'e' | 'E') {
+            let mut end = self.position + 1;
+            if end < self.chars.len() && matches!(self.chars[end], '+' | '-') {
+                end += 1;
+            }
+            if end < self.chars.len() && self.chars[end].is_ascii_digit() {
+                self.position = end;
+                while self.position < self.chars.len() && self.chars[self.position].is_ascii_digit() {
+                    self.position += 1;
+                }
+            }
+        }
+
+        let text: String = self.chars[start..self.position].iter().collect();
+        match text.parse::<f64>() {
+            Ok(value) => Ok(Node::Number(value)),
+            Err(_) => Err(ExpressionError {
+                msg: format!("invalid number '{}'", text),
+                position: start,
+            }),
+        }
+    }
+
+    fn identifier(&mut self) -> Result<Node, ExpressionError> {
+        let start = self.position;
+        while self.position < self.chars.len()
+            && (self.chars[self.position].is_ascii_alphanumeric() || self.chars[self.position] == '_')
+        {
+            self.position += 1;
+        }
+        let name: String = self.chars[start..self.position].iter().collect();
+
+        if self.peek() == Some('(') {
+            let Some((function, arity)) = Function::from_name(&name) else {
+                return Err(ExpressionError {
+                    msg: format!("unknown function '{}'", name),
+                    position: start,
+                });
+            };
+            self.position += 1;
+
+            let mut args: Vec<Node> = Vec::new();
+            if !self.consume(")") {
+                loop {
+                    args.push(self.comparison()?);
+                    if self.consume(")") {
+                        break;
+                    }
+                    if !self.consume(",") {
+                        return Err(self.error("expected ',' or ')'"));
+                    }
+                }
+            }
+            if args.len() != arity {
+                return Err(ExpressionError {
+                    msg: format!("{} takes {} arguments, got {}", name, arity, args.len()),
+                    position: start,
+                });
+            }
+            return Ok(Node::Call(function, args));
+        }
+
+        let node = match name.as_str() {
+            "x" => Node::Variable(Variable::X),
+            "y" => Node::Variable(Variable::Y),
+            "width" => Node::Variable(Variable::Width),
+            "height" => Node::Variable(Variable::Height),
+            "luminance" => Node::Variable(Variable::Luminance),
+            "pi" => Node::Number(consts::PI),
+            "e" => Node::Number(consts::E),
+            _ => {
+                return Err(ExpressionError {
+                    msg: format!("unknown variable '{}'", name),
+                    position: start,
+                })
+            }
+        };
+        Ok(node)
+    }
+}
+
+#[derive(Debug)]
+pub struct ExpressionError {
+    msg: String,
+    position: usize,
+}
+
+impl Display for ExpressionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("ExpressionError {} at {}", self.msg, self.position))
+    }
+}
+impl Error for ExpressionError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(source: &str, x: f64, y: f64) -> f64 {
+        ThresholdExpr::parse(source).unwrap().evaluate(&Variables {
+            x,
+            y,
+            width: 100.0,
+            height: 50.0,
+            luminance: 0.25,
+        })
+    }
+
+    #[test]
+    fn test_precedence() {
+        assert_eq!(eval("1 + 2 * 3", 0.0, 0.0), 7.0);
+        assert_eq!(eval("(1 + 2) * 3", 0.0, 0.0), 9.0);
+        assert_eq!(eval("2 ^ 3 ^ 2", 0.0, 0.0), 512.0);
+        assert_eq!(eval("-2 ^ 2", 0.0, 0.0), -4.0);
+        assert_eq!(eval("1 - 2 - 3", 0.0, 0.0), -4.0);
+        assert_eq!(eval("1 + 1 < 3", 0.0, 0.0), 1.0);
+        assert_eq!(eval("2.5e1 + .5", 0.0, 0.0), 25.5);
+    }
+
+    #[test]
+    fn test_variables_and_functions() {
+        assert_eq!(eval("fract((x + y) / 6)", 4.0, 5.0), 0.5);
+        assert_eq!(eval("x % 4", -1.0, 0.0), 3.0);
+        assert_eq!(eval("fract(-0.25)", 0.0, 0.0), 0.75);
+        assert_eq!(eval("width / height * luminance", 0.0, 0.0), 0.5);
+        assert_eq!(eval("clamp(x, 0, 1)", 7.0, 0.0), 1.0);
+        assert_eq!(eval("mix(0, 10, 0.25)", 0.0, 0.0), 2.5);
+        assert_eq!(eval("length(x, y)", 3.0, 4.0), 5.0);
+        let hash = eval("fract(sin(x*12.9898 + y*78.233) * 43758.5453)", 3.0, 7.0);
+        assert!((0.0..1.0).contains(&hash));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(ThresholdExpr::parse("").is_err());
+        assert!(ThresholdExpr::parse("x +").is_err());
+        assert!(ThresholdExpr::parse("(x").is_err());
+        assert!(ThresholdExpr::parse("z").is_err());
+        assert!(ThresholdExpr::parse("sin(x, y)").is_err());
+        assert!(ThresholdExpr::parse("foo(x)").is_err());
+        assert!(ThresholdExpr::parse("x y").is_err());
+        // too deep for the parser, or too long
+        assert!(ThresholdExpr::parse(&format!("{}x{}", "(".repeat(100000), ")".repeat(100000))).is_err());
+        assert!(ThresholdExpr::parse(&"-".repeat(100000)).is_err());
+        assert!(ThresholdExpr::parse(&"1+".repeat(100000)).is_err());
+        assert!(ThresholdExpr::parse(&format!("{}x{}", "(".repeat(32), ")".repeat(32))).is_ok());
+    }
+}
@@ -0,0 +1,163 @@
+use std::fmt;
+
+use image::DynamicImage;
+
+use crate::{
+    color_palette::ColorMapElement,
+    dithering::{threshold::dither_helper, DitherOptions, DitheringType},
+    image_utils::gaussian_blur,
+    pixel_util::RGB,
+};
+
+// a grey map of what matters in the image (white) and what doesn't (black), like a depth or a
+// saliency map. every setting is what the least important pixels get, the most important ones
+// are left as they are and everything in between is interpolated
+#[derive(Clone)]
+pub struct Importance {
+    pub path: String,
+    pub image: DynamicImage,
+    // black is what matters, for depth maps where far is white
+    pub invert: bool,
+    // standard deviation of the blur, in processing pixels
+    pub blur: f64,
+    // factor of the contrast around mid grey, below 1.0 flattens the tones
+    pub contrast: f64,
+    // factor of the spread of the thresholds around 0.5, 0.0 is a plain quantization
+    pub threshold_scale: f64,
+    // size of the threshold pattern cells in pixels, above 1.0 coarsens the pattern
+    pub pattern_size: f64,
+}
+
+impl Default for Importance {
+    fn default() -> Self {
+        Importance {
+            path: String::new(),
+            image: DynamicImage::new_luma8(1, 1),
+            invert: false,
+            blur: 0.0,
+            contrast: 1.0,
+            threshold_scale: 1.0,
+            pattern_size: 1.0,
+        }
+    }
+}
+
+// the pixels of the map don't belong in the config dumps
+impl fmt::Debug for Importance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Importance")
+            .field("path", &self.path)
+            .field("invert", &self.invert)
+            .field("blur", &self.blur)
+            .field("contrast", &self.contrast)
+            .field("threshold_scale", &self.threshold_scale)
+            .field("pattern_size", &self.pattern_size)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Importance {
+    // importance of a pixel from the grey level of the map
+    fn importance(&self, weight: f64) -> f64 {
+        if self.invert {
+            1.0 - weight
+        } else {
+            weight
+        }
+    }
+
+    // the value of the setting for a pixel of the given grey level
+    fn at(&self, setting: f64, weight: f64) -> f64 {
+        setting + (1.0 - setting) * self.importance(weight)
+    }
+
+    // blurs and flattens the luminance where it doesn't matter
+    pub fn adjust_tones(&self, luminance: &[f64], weights: &[f64], width: u32, height: u32) -> Vec<f64> {
+        // a map or a luminance that doesn't match the size leaves the tones as they are
+        if luminance.len() != width as usize * height as usize || weights.len() != luminance.len() {
+            return luminance.to_vec();
+        }
+        let blurred = gaussian_blur(luminance, width, height, self.blur);
+
+        (0..luminance.len())
+            .map(|index| {
+                let importance = self.importance(weights[index]);
+                let value = blurred[index] + (luminance[index] - blurred[index]) * importance;
+                (0.5 + (value - 0.5) * self.at(self.contrast, weights[index])).clamp(0.0, 1.0)
+            })
+            .collect()
+    }
+
+    // ordered dithering with thresholds spread less and cells made larger where it doesn't
+    // matter. types without thresholds, like error diffusion, dither as usual
+    #[allow(clippy::too_many_arguments)]
+    pub fn dither(
+        &self,
+        data: &mut [RGB],
+        weights: &[f64],
+        width: u32,
+        height: u32,
+        dithering_type: &DitheringType,
+        color_map: &[ColorMapElement],
+        options: &DitherOptions,
+    ) {
+        // like DitheringType::dither, the pixels are left as they are
+        if color_map.is_empty() || data.len() != width as usize * height as usize || weights.len() != data.len() {
+            return;
+        }
+        let thresholds = match dithering_type.threshold_map(width, height, options) {
+            Some(thresholds) => thresholds,
+            None => return dithering_type.dither(data, width, height, color_map, options),
+        };
+
+        let w = width as usize;
+        for (index, pixel) in data.iter_mut().enumerate() {
+            let size = self.at(self.pattern_size, weights[index]).max(1.0);
+            // the whole cell uses the threshold of its first pixel
+            let (x, y) = ((index % w) as f64 / size, (index / w) as f64 / size);
+            let threshold = thresholds[y as usize * w + x as usize];
+            let scale = self.at(self.threshold_scale, weights[index]);
+            if let Some(color) = dither_helper(pixel.grayscale(), 0.5 + (threshold - 0.5) * scale, color_map) {
+                *pixel = color;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color_palette::DEFAULT_COLOR_MAP;
+
+    #[test]
+    fn test_unimportant_areas_are_flattened() {
+        let (width, height) = (32, 8);
+        let luminance = (0..width * height)
+            .map(|index| if index % 2 == 0 { 0.3 } else { 0.6 })
+            .collect::<Vec<f64>>();
+        // only the left half matters
+        let weights = (0..width * height)
+            .map(|index| if index % width < width / 2 { 1.0 } else { 0.0 })
+            .collect::<Vec<f64>>();
+        let importance = Importance {
+            contrast: 0.0,
+            threshold_scale: 0.0,
+            ..Default::default()
+        };
+
+        let adjusted = importance.adjust_tones(&luminance, &weights, width as u32, height as u32);
+        assert!((adjusted[0] - 0.3).abs() < 1e-9);
+        assert!((adjusted[width - 1] - 0.5).abs() < 1e-9);
+
+        // without any spread of the thresholds, a flat grey just above mid grey turns white
+        let mut data = adjusted
+            .iter()
+            .map(|value| RGB { r: *value + 0.01, g: *value + 0.01, b: *value + 0.01, a: 1.0 })
+            .collect::<Vec<RGB>>();
+        let options = DitherOptions::default();
+        importance.dither(&mut data, &weights, width as u32, height as u32, &DitheringType::Bayer1, &DEFAULT_COLOR_MAP, &options);
+        let white = DEFAULT_COLOR_MAP.last().unwrap().color;
+        assert!((0..width * height).filter(|index| index % width >= width / 2).all(|index| data[index] == white));
+        assert!(data.iter().any(|pixel| *pixel != white));
+    }
+}
@@ -0,0 +1,164 @@
+use std::fmt;
+
+use image::DynamicImage;
+
+use crate::{
+    color_palette::ColorMapElement,
+    dithering::{
+        threshold::{dither_helper, ThresholdType},
+        DitherOptions, DitheringType,
+    },
+    pixel_util::RGB,
+};
+
+// a grey image choosing, pixel by pixel, between the settings of the config (black) and the
+// settings of the masked region (white)
+#[derive(Clone)]
+pub struct Mask {
+    pub path: String,
+    pub image: DynamicImage,
+    // grey pixels blend both sides instead of being cut at half
+    pub soft: bool,
+    pub dithering_type: DitheringType,
+    pub color_map: Vec<ColorMapElement>,
+    pub brigthness_delta: i32,
+    pub constrast_delta: f32,
+}
+
+// the pixels of the mask don't belong in the config dumps
+impl fmt::Debug for Mask {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mask")
+            .field("path", &self.path)
+            .field("soft", &self.soft)
+            .field("dithering_type", &self.dithering_type)
+            .field("color_map", &self.color_map)
+            .field("brigthness_delta", &self.brigthness_delta)
+            .field("constrast_delta", &self.constrast_delta)
+            .finish_non_exhaustive()
+    }
+}
+
+// one side of the mask: the image prepared with its tones, its dithering type and color map
+pub struct Side<'a> {
+    pub data: &'a [RGB],
+    pub dithering_type: &'a DitheringType,
+    pub color_map: &'a [ColorMapElement],
+}
+
+// dithers both sides and mixes them following the weights of the mask (0.0 for the outside,
+// 1.0 for the masked region).
+// a soft mask between two threshold based types blends their thresholds and the tones of both
+// sides, so patterns morph into each other. when either side has no threshold map, like error
+// diffusion, the transition is made by dithering the mask itself with blue noise
+pub fn dither(
+    outside: &Side,
+    inside: &Side,
+    weights: &[f64],
+    width: u32,
+    height: u32,
+    soft: bool,
+    options: &DitherOptions,
+) -> Vec<RGB> {
+    // like DitheringType::dither, pixels that don't match the size or an empty color map leave
+    // the outside as it is
+    let size = width as usize * height as usize;
+    let sizes = [outside.data.len(), inside.data.len(), weights.len()];
+    if sizes.iter().any(|len| *len != size) || outside.color_map.is_empty() || inside.color_map.is_empty() {
+        return outside.data.to_vec();
+    }
+    if soft {
+        let thresholds = (
+            outside.dithering_type.threshold_map(width, height, options),
+            inside.dithering_type.threshold_map(width, height, options),
+        );
+        if let (Some(outside_thresholds), Some(inside_thresholds)) = thresholds {
+            return (0..weights.len())
+                .map(|index| {
+                    let weight = weights[index];
+                    let value = lerp(outside.data[index].grayscale(), inside.data[index].grayscale(), weight);
+                    let threshold = lerp(outside_thresholds[index], inside_thresholds[index], weight);
+                    let color_map = if weight < 0.5 { outside.color_map } else { inside.color_map };
+                    dither_helper(value, threshold, color_map).unwrap_or(outside.data[index])
+                })
+                .collect();
+        }
+    }
+
+    let dithered = |side: &Side| {
+        let mut data = side.data.to_vec();
+        side.dithering_type.dither(&mut data, width, height, side.color_map, options);
+        data
+    };
+    let (outside_pixels, inside_pixels) = (dithered(outside), dithered(inside));
+
+    let cuts = if soft {
+        ThresholdType::BlueNoise.threshold_map(width, height, options)
+    } else {
+        vec![0.5; weights.len()]
+    };
+    (0..weights.len())
+        .map(|index| {
+            if weights[index] >= cuts[index] {
+                inside_pixels[index]
+            } else {
+                outside_pixels[index]
+            }
+        })
+        .collect()
+}
+
+fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    a + (b - a) * t
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color_palette::DEFAULT_COLOR_MAP;
+
+    #[test]
+    fn test_mask_picks_the_side() {
+        let (width, height) = (16, 8);
+        let grey = vec![RGB { r: 0.5, g: 0.5, b: 0.5, a: 1.0 }; width * height];
+        // the right half is masked
+        let weights = (0..width * height)
+            .map(|index| if index % width < width / 2 { 0.0 } else { 1.0 })
+            .collect::<Vec<f64>>();
+        let red = [
+            ColorMapElement { color: RGB::from_u8(0, 0, 0, 255), scale: 1.0, offset: 0.0 },
+            ColorMapElement { color: RGB::from_u8(255, 0, 0, 255), scale: 1.0, offset: 0.0 },
+        ];
+        let outside = Side { data: &grey, dithering_type: &DitheringType::Bayer1, color_map: &DEFAULT_COLOR_MAP };
+        let inside = Side { data: &grey, dithering_type: &DitheringType::Atkinson, color_map: &red };
+
+        for soft in [false, true] {
+            let mixed = dither(&outside, &inside, &weights, width as u32, height as u32, soft, &DitherOptions::default());
+
+            let mut expected = grey.clone();
+            DitheringType::Bayer1.dither(&mut expected, width as u32, height as u32, &DEFAULT_COLOR_MAP, &DitherOptions::default());
+            for (index, pixel) in mixed.iter().enumerate() {
+                if index % width < width / 2 {
+                    assert_eq!(*pixel, expected[index]);
+                } else {
+                    assert!(red.iter().any(|element| element.color == *pixel));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_soft_mask_blends_thresholds() {
+        let (width, height) = (64, 1);
+        let grey = vec![RGB { r: 0.5, g: 0.5, b: 0.5, a: 1.0 }; width];
+        let weights = vec![0.5; width];
+        let outside = Side { data: &grey, dithering_type: &DitheringType::Bayer0, color_map: &DEFAULT_COLOR_MAP };
+        let inside = Side { data: &grey, dithering_type: &DitheringType::Bayer0, color_map: &DEFAULT_COLOR_MAP };
+
+        // the same thresholds on both sides blend into themselves
+        let mixed = dither(&outside, &inside, &weights, width as u32, height as u32, true, &DitherOptions::default());
+        let mut expected = grey.clone();
+        DitheringType::Bayer0.dither(&mut expected, width as u32, height as u32, &DEFAULT_COLOR_MAP, &DitherOptions::default());
+        assert_eq!(mixed, expected);
+    }
+}
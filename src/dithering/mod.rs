@@ -1,9 +1,45 @@
-use crate::{color_palette::ColorMapElement, dithering::{error_diffusion::ErrorDiffusionType, threshold::ThresholdType}, pixel_util::RGB};
+use crate::{
+    color_palette::ColorMapElement,
+    dithering::{
+        dot_diffusion::ClassMatrix,
+        error_diffusion::ErrorDiffusionType,
+        expression::{ThresholdExpr, Variables},
+        noise::{NoiseDistribution, NoiseSource},
+        threshold::{dither_with_thresholds, ThresholdType},
+    },
+    pixel_util::RGB,
+};
 
+pub mod dbs;
+pub mod dot_diffusion;
 mod error_diffusion;
+pub mod expression;
+pub mod importance;
+pub mod mask;
+pub mod noise;
+mod riemersma;
 mod threshold;
 
-#[derive(Debug, Clone, Copy)]
+// settings shared by every dithering type
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DitherOptions {
+    // makes random dithering reproducible
+    pub seed: Option<u64>,
+    pub noise: NoiseDistribution,
+    // random values depend on the pixel position instead of the order pixels are visited in
+    pub hashed_noise: bool,
+    // the output tiles seamlessly: threshold patterns fit a whole number of times in the image
+    // and error diffusion wraps around the borders
+    pub tileable: bool,
+}
+
+impl DitherOptions {
+    pub fn noise_source(&self) -> NoiseSource {
+        NoiseSource::new(self.seed, self.noise, self.hashed_noise)
+    }
+}
+
+#[derive(Debug, Clone)]
 pub enum DitheringType {
     Rand,
     Bayer0,
@@ -11,29 +47,161 @@ pub enum DitheringType {
     Bayer2,
     Bayer3,
     BlueNoise,
+    InterleavedGradientNoise,
+    R2,
+    GoldenRatio,
+    HashNoise,
     FloydSteinberg,
     JarvisJudiceNinke,
     Atkinson,
+    Ostromoukhov,
+    ZhouFang,
+    Riemersma,
+    // dot diffusion with Knuth's class matrix
+    DotDiffusion,
+    // dot diffusion with a class matrix from the config
+    CustomDotDiffusion(ClassMatrix),
+    // threshold given by a formula from the config
+    Expression(ThresholdExpr),
 }
 
 impl DitheringType {
+    pub const ALL: [DitheringType; 17] = [
+        Self::Rand,
+        Self::Bayer0,
+        Self::Bayer1,
+        Self::Bayer2,
+        Self::Bayer3,
+        Self::BlueNoise,
+        Self::InterleavedGradientNoise,
+        Self::R2,
+        Self::GoldenRatio,
+        Self::HashNoise,
+        Self::FloydSteinberg,
+        Self::JarvisJudiceNinke,
+        Self::Atkinson,
+        Self::Ostromoukhov,
+        Self::ZhouFang,
+        Self::Riemersma,
+        Self::DotDiffusion,
+    ];
+
+    // name used in the config file and on the command line, expressions are written as "expr"
+    // along with a "threshold_expr" field, custom class matrices along with a "class_matrix" field
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Rand => "rand",
+            Self::Bayer0 => "bayer_0",
+            Self::Bayer1 => "bayer_1",
+            Self::Bayer2 => "bayer_2",
+            Self::Bayer3 => "bayer_3",
+            Self::BlueNoise => "blue_noise",
+            Self::InterleavedGradientNoise => "ign",
+            Self::R2 => "r2",
+            Self::GoldenRatio => "golden_ratio",
+            Self::HashNoise => "hash_noise",
+            Self::FloydSteinberg => "floyd",
+            Self::JarvisJudiceNinke => "jarvis",
+            Self::Atkinson => "atkinson",
+            Self::Ostromoukhov => "ostromoukhov",
+            Self::ZhouFang => "zhou_fang",
+            Self::Riemersma => "riemersma",
+            Self::DotDiffusion | Self::CustomDotDiffusion(_) => "dot_diffusion",
+            Self::Expression(_) => "expr",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<DitheringType> {
+        Self::ALL.into_iter().find(|dtype| dtype.name() == name)
+    }
+
+    // whether the output tiles seamlessly with options.tileable, the golden ratio sequence and
+    // expressions have no period to align, and the Hilbert curve can't carry its error across
+    pub fn wraps(&self) -> bool {
+        !matches!(self, Self::GoldenRatio | Self::Riemersma | Self::Expression(_))
+    }
+
+    // the thresholds used by ordered dithering, None for error diffusion which has none
+    pub fn threshold_map(&self, width: u32, height: u32, options: &DitherOptions) -> Option<Vec<f64>> {
+        let threshold_type = match self {
+            Self::Rand => ThresholdType::Rand,
+            Self::Bayer0 => ThresholdType::Bayer0,
+            Self::Bayer1 => ThresholdType::Bayer1,
+            Self::Bayer2 => ThresholdType::Bayer2,
+            Self::Bayer3 => ThresholdType::Bayer3,
+            Self::BlueNoise => ThresholdType::BlueNoise,
+            Self::InterleavedGradientNoise => ThresholdType::InterleavedGradientNoise,
+            Self::R2 => ThresholdType::R2,
+            Self::GoldenRatio => ThresholdType::GoldenRatio,
+            Self::HashNoise => ThresholdType::HashNoise,
+            Self::FloydSteinberg
+            | Self::JarvisJudiceNinke
+            | Self::Atkinson
+            | Self::Ostromoukhov
+            | Self::ZhouFang
+            | Self::Riemersma
+            | Self::DotDiffusion
+            | Self::CustomDotDiffusion(_) => return None,
+            // the luminance of a mid grey stands in for the pixel
+            Self::Expression(expr) => {
+                return Some(
+                    (0..(width * height) as usize)
+                        .map(|index| {
+                            expr.evaluate(&Variables {
+                                x: (index % width as usize) as f64,
+                                y: (index / width as usize) as f64,
+                                width: width as f64,
+                                height: height as f64,
+                                luminance: 0.5,
+                            })
+                        })
+                        .collect(),
+                )
+            }
+        };
+        Some(threshold_type.threshold_map(width, height, options))
+    }
+
+    // an empty color map, or pixels that don't match the size, leave the pixels as they are
     pub fn dither(
         &self,
-        data: &mut Vec<RGB>,
+        data: &mut [RGB],
         width: u32,
         height: u32,
-        color_map: &Vec<ColorMapElement>,
+        color_map: &[ColorMapElement],
+        options: &DitherOptions,
     ) {
+        if color_map.is_empty() || data.len() != width as usize * height as usize {
+            return;
+        }
         match self {
-            Self::Rand => ThresholdType::Rand.dither(data, width, height, color_map),
-            Self::Bayer0 => ThresholdType::Bayer0.dither(data, width, height, color_map),
-            Self::Bayer1 => ThresholdType::Bayer1.dither(data, width, height, color_map),
-            Self::Bayer2 => ThresholdType::Bayer2.dither(data, width, height, color_map),
-            Self::Bayer3 => ThresholdType::Bayer3.dither(data, width, height, color_map),
-            Self::BlueNoise => ThresholdType::BlueNoise.dither(data, width, height, color_map),
-            Self::FloydSteinberg => ErrorDiffusionType::FloydSteinberg.dither(data, width, height, color_map),
-            Self::JarvisJudiceNinke => ErrorDiffusionType::JarvisJudiceNinke.dither(data, width, height, color_map),
-            Self::Atkinson => ErrorDiffusionType::Atkinson.dither(data, width, height, color_map),
+            Self::Rand => ThresholdType::Rand.dither(data, width, height, color_map, options),
+            Self::Bayer0 => ThresholdType::Bayer0.dither(data, width, height, color_map, options),
+            Self::Bayer1 => ThresholdType::Bayer1.dither(data, width, height, color_map, options),
+            Self::Bayer2 => ThresholdType::Bayer2.dither(data, width, height, color_map, options),
+            Self::Bayer3 => ThresholdType::Bayer3.dither(data, width, height, color_map, options),
+            Self::BlueNoise => ThresholdType::BlueNoise.dither(data, width, height, color_map, options),
+            Self::InterleavedGradientNoise => ThresholdType::InterleavedGradientNoise.dither(data, width, height, color_map, options),
+            Self::R2 => ThresholdType::R2.dither(data, width, height, color_map, options),
+            Self::GoldenRatio => ThresholdType::GoldenRatio.dither(data, width, height, color_map, options),
+            Self::HashNoise => ThresholdType::HashNoise.dither(data, width, height, color_map, options),
+            Self::FloydSteinberg => ErrorDiffusionType::FloydSteinberg.dither(data, width, height, color_map, options),
+            Self::JarvisJudiceNinke => ErrorDiffusionType::JarvisJudiceNinke.dither(data, width, height, color_map, options),
+            Self::Atkinson => ErrorDiffusionType::Atkinson.dither(data, width, height, color_map, options),
+            Self::Ostromoukhov => ErrorDiffusionType::Ostromoukhov.dither(data, width, height, color_map, options),
+            Self::ZhouFang => ErrorDiffusionType::ZhouFang.dither(data, width, height, color_map, options),
+            Self::Riemersma => riemersma::dither(data, width, height, color_map),
+            Self::DotDiffusion => dot_diffusion::dither(data, width, height, color_map, &ClassMatrix::knuth(), options.tileable),
+            Self::CustomDotDiffusion(class_matrix) => dot_diffusion::dither(data, width, height, color_map, class_matrix, options.tileable),
+            Self::Expression(expr) => dither_with_thresholds(data, width, color_map, |x, y, luminance| {
+                expr.evaluate(&Variables {
+                    x: x as f64,
+                    y: y as f64,
+                    width: width as f64,
+                    height: height as f64,
+                    luminance,
+                })
+            }),
         };
     }
 }
@@ -0,0 +1,171 @@
+use rand::Rng;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum NoiseDistribution {
+    #[default]
+    Uniform,
+    // sum of two uniform values, the values cluster around the middle
+    Triangular,
+    // normal distribution centered on 0.5, clamped to [0.0, 1.0]
+    Gaussian,
+}
+
+impl NoiseDistribution {
+    pub const ALL: [NoiseDistribution; 3] = [Self::Uniform, Self::Triangular, Self::Gaussian];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Uniform => "uniform",
+            Self::Triangular => "triangular",
+            Self::Gaussian => "gaussian",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<NoiseDistribution> {
+        Self::ALL.into_iter().find(|distribution| distribution.name() == name)
+    }
+}
+
+// standard deviation of the gaussian noise, so that +-3 sigmas fit in [0.0, 1.0]
+const GAUSSIAN_SIGMA: f64 = 1.0 / 6.0;
+
+// values in [0.0, 1.0] for random dithering, reproducible when a seed is given
+pub struct NoiseSource {
+    seed: u64,
+    distribution: NoiseDistribution,
+    position_hashed: bool,
+    rng: SplitMix,
+}
+
+impl NoiseSource {
+    // without a seed every source is different
+    pub fn new(seed: Option<u64>, distribution: NoiseDistribution, position_hashed: bool) -> NoiseSource {
+        let seed = seed.unwrap_or_else(|| rand::rng().random());
+        NoiseSource {
+            seed,
+            distribution,
+            position_hashed,
+            rng: SplitMix::new(seed),
+        }
+    }
+
+    // the value for the pixel at (x, y), a position hashed source gives the same value for the
+    // same pixel no matter the order pixels are visited in, otherwise values come one after another
+    pub fn next(&mut self, x: usize, y: usize) -> f64 {
+        match self.distribution {
+            NoiseDistribution::Uniform => self.uniform(x, y, 0),
+            NoiseDistribution::Triangular => (self.uniform(x, y, 0) + self.uniform(x, y, 1)) / 2.0,
+            NoiseDistribution::Gaussian => {
+                // Box-Muller, 1 - u keeps the logarithm away from 0
+                let u1 = 1.0 - self.uniform(x, y, 0);
+                let u2 = self.uniform(x, y, 1);
+                let normal = (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos();
+                (0.5 + normal * GAUSSIAN_SIGMA).clamp(0.0, 1.0)
+            }
+        }
+    }
+
+    // uniform value hashed from the position, whatever the settings of the source
+    pub fn position_value(&self, x: usize, y: usize) -> f64 {
+        hash_to_unit(hash(self.seed, x as u64, y as u64, 0))
+    }
+
+    fn uniform(&mut self, x: usize, y: usize, stream: u64) -> f64 {
+        if self.position_hashed {
+            hash_to_unit(hash(self.seed, x as u64, y as u64, stream))
+        } else {
+            self.rng.next_unit()
+        }
+    }
+}
+
+// a well mixed 64 bit hash of a position (splitmix64 finalizer over the combined inputs)
+pub fn hash(seed: u64, x: u64, y: u64, stream: u64) -> u64 {
+    let mut z = seed
+        ^ x.wrapping_mul(0x9E3779B97F4A7C15)
+        ^ y.wrapping_mul(0xC2B2AE3D27D4EB4F)
+        ^ stream.wrapping_mul(0x165667B19E3779F9);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+// splitmix64 stream, unlike the generators of rand its output is fixed for a given seed
+// whatever the version of the dependencies, so seeded outputs stay the same
+pub struct SplitMix {
+    state: u64,
+}
+
+impl SplitMix {
+    pub fn new(seed: u64) -> SplitMix {
+        SplitMix { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    // uniform value in [0.0, 1.0)
+    pub fn next_unit(&mut self) -> f64 {
+        hash_to_unit(self.next_u64())
+    }
+}
+
+// uniform value in [0.0, 1.0) out of the 53 high bits of a hash
+pub fn hash_to_unit(hash: u64) -> f64 {
+    (hash >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seeded_noise_is_reproducible() {
+        for distribution in NoiseDistribution::ALL {
+            for position_hashed in [false, true] {
+                let mut a = NoiseSource::new(Some(7), distribution, position_hashed);
+                let mut b = NoiseSource::new(Some(7), distribution, position_hashed);
+                for i in 0..100 {
+                    assert_eq!(a.next(i % 10, i / 10), b.next(i % 10, i / 10));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_split_mix_is_stable() {
+        // reference values of splitmix64 seeded with 0
+        let mut rng = SplitMix::new(0);
+        assert_eq!(rng.next_u64(), 0xE220A8397B1DCDAF);
+        assert_eq!(rng.next_u64(), 0x6E789E6AA1B965F4);
+        assert_eq!(rng.next_u64(), 0x06C45D188009454F);
+    }
+
+    #[test]
+    fn test_hashed_noise_ignores_order() {
+        let mut forward = NoiseSource::new(Some(7), NoiseDistribution::Uniform, true);
+        let mut backward = NoiseSource::new(Some(7), NoiseDistribution::Uniform, true);
+        let forward_values = (0..100).map(|i| forward.next(i % 10, i / 10)).collect::<Vec<f64>>();
+        let mut backward_values = (0..100).rev().map(|i| backward.next(i % 10, i / 10)).collect::<Vec<f64>>();
+        backward_values.reverse();
+
+        assert_eq!(forward_values, backward_values);
+    }
+
+    #[test]
+    fn test_distributions_are_centered() {
+        for distribution in NoiseDistribution::ALL {
+            let mut source = NoiseSource::new(Some(1), distribution, true);
+            let values = (0..10000).map(|i| source.next(i % 100, i / 100)).collect::<Vec<f64>>();
+            let mean = values.iter().sum::<f64>() / values.len() as f64;
+
+            assert!((mean - 0.5).abs() < 0.02);
+            assert!(values.iter().all(|v| (0.0..=1.0).contains(v)));
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use crate::{
+    color_palette::ColorMapElement,
+    dithering::error_diffusion::{nearest_level, normalize_color_map},
+    pixel_util::RGB,
+};
+
+// number of past errors carried along the curve
+const QUEUE_SIZE: usize = 16;
+// ratio between the weight of the newest and the oldest error
+const WEIGHT_RATIO: f64 = 16.0;
+
+// Riemersma's dithering: pixels are visited along a Hilbert curve and every pixel receives the
+// errors of the last QUEUE_SIZE visited ones, with exponentially decaying weights.
+// Since the curve keeps turning there is no preferred direction for artifacts to line up on.
+pub fn dither(data: &mut [RGB], width: u32, height: u32, color_map: &[ColorMapElement]) {
+    let mut n_color_map = color_map.to_vec();
+    normalize_color_map(&mut n_color_map);
+
+    // the oldest error gets 1 / WEIGHT_RATIO, the newest 1
+    let weights = (0..QUEUE_SIZE)
+        .map(|i| WEIGHT_RATIO.powf(i as f64 / (QUEUE_SIZE - 1) as f64) / WEIGHT_RATIO)
+        .collect::<Vec<f64>>();
+    let mut errors = [0.0; QUEUE_SIZE];
+
+    // the smallest power of two square covering the image, quadrants outside are skipped
+    let side = width.max(height).max(1).next_power_of_two() as u64;
+    hilbert_walk(side, (width as u64, height as u64), 0, side, &mut |x, y| {
+        let index = (y * width as u64 + x) as usize;
+
+        let original = data[index].grayscale();
+        let carried: f64 = errors.iter().zip(weights.iter()).map(|(e, w)| e * w).sum();
+        let element = n_color_map[nearest_level(original + carried, &n_color_map)];
+
+        errors.rotate_left(1);
+        // like the original algorithm, the error is measured against the pixel alone
+        errors[QUEUE_SIZE - 1] = original - element.scale;
+        data[index] = element.color;
+    });
+}
+
+// visits in order the points of the curve inside width x height, out of the block of size x size
+// points starting at the given distance. blocks of the curve fill aligned squares, so the ones
+// outside are skipped whole and a thin image doesn't walk the whole square
+fn hilbert_walk(side: u64, (width, height): (u64, u64), start: u64, size: u64, visit: &mut impl FnMut(u64, u64)) {
+    let (x, y) = hilbert_point(side, start);
+    let (left, top) = (x / size * size, y / size * size);
+    if left >= width || top >= height {
+        return;
+    }
+    if left + size <= width && top + size <= height {
+        for distance in start..start + size * size {
+            let (x, y) = hilbert_point(side, distance);
+            visit(x, y);
+        }
+        return;
+    }
+    let quarter = size / 2;
+    for block in 0..4 {
+        hilbert_walk(side, (width, height), start + block * quarter * quarter, quarter, visit);
+    }
+}
+
+// position of the point at the given distance along a Hilbert curve filling a side x side square
+fn hilbert_point(side: u64, distance: u64) -> (u64, u64) {
+    let (mut x, mut y) = (0, 0);
+    let mut t = distance;
+    let mut s = 1;
+    while s < side {
+        let rx = 1 & (t / 2);
+        let ry = 1 & (t ^ rx);
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - x;
+                y = s - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        x += s * rx;
+        y += s * ry;
+        t /= 4;
+        s *= 2;
+    }
+    (x, y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hilbert_curve_is_continuous() {
+        let side = 16;
+        let mut visited = vec![false; (side * side) as usize];
+        let mut previous = hilbert_point(side, 0);
+        visited[0] = previous == (0, 0);
+
+        for distance in 1..side * side {
+            let point = hilbert_point(side, distance);
+            // every step moves to a neighbour
+            assert_eq!(point.0.abs_diff(previous.0) + point.1.abs_diff(previous.1), 1);
+            visited[(point.1 * side + point.0) as usize] = true;
+            previous = point;
+        }
+        assert!(visited.iter().all(|v| *v));
+    }
+
+    #[test]
+    fn test_walk_keeps_the_curve_order() {
+        let side = 16;
+        for (width, height) in [(16, 16), (13, 5), (1, 16), (16, 1), (9, 9)] {
+            let mut walked = Vec::new();
+            hilbert_walk(side, (width, height), 0, side, &mut |x, y| walked.push((x, y)));
+            let expected = (0..side * side)
+                .map(|distance| hilbert_point(side, distance))
+                .filter(|(x, y)| *x < width && *y < height)
+                .collect::<Vec<(u64, u64)>>();
+            assert_eq!(walked, expected);
+        }
+    }
+}
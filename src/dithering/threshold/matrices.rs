@@ -48,7 +48,7 @@ pub const BAYER3: [f64; 256] = [
 ];
 
 
-pub const BLUE_NOISE: [f64; 16384] = [
+pub static BLUE_NOISE: [f64; 16384] = [
     0.28627450980392155,
     0.0784313725490196,
     0.8156862745098039,
@@ -2,12 +2,13 @@ mod matrices;
 
 use crate::{
     color_palette::ColorMapElement,
-    dithering::threshold::{
-        matrices::{BAYER0, BAYER1, BAYER2, BAYER3, BLUE_NOISE},
+    dithering::{
+        noise::NoiseSource,
+        threshold::matrices::{BAYER0, BAYER1, BAYER2, BAYER3, BLUE_NOISE},
+        DitherOptions,
     },
     pixel_util::RGB,
 };
-use rand::Rng;
 
 #[derive(Debug, Clone, Copy)]
 pub enum ThresholdType {
@@ -17,54 +18,172 @@ pub enum ThresholdType {
     Bayer2,
     Bayer3,
     BlueNoise,
+    // procedural thresholds, computed straight from the position so they never tile
+    InterleavedGradientNoise,
+    R2,
+    GoldenRatio,
+    HashNoise,
 }
 
+// plastic number, the 2D generalization of the golden ratio used by the R2 sequence
+const PLASTIC: f64 = 1.324_717_957_244_746;
+const GOLDEN: f64 = 1.618_033_988_749_895;
+
 impl ThresholdType {
     pub fn dither(
         self,
-        data: &mut Vec<RGB>,
+        data: &mut [RGB],
         width: u32,
-        _height: u32,
-        color_map: &Vec<ColorMapElement>,
+        height: u32,
+        color_map: &[ColorMapElement],
+        options: &DitherOptions,
     ) {
-        let mut index = 0;
-        while index < data.len() {
-            data[index] = self.dither_helper(
-                data[index].grayscale(),
-                color_map,
-                index % width as usize,
-                index / width as usize,
-            );
-
-            index += 1;
-        }
+        let mut noise = options.noise_source();
+        let tile = options.tileable.then_some((width as usize, height as usize));
+        dither_with_thresholds(data, width, color_map, |x, y, _| {
+            self.get_threshold(x, y, &mut noise, tile)
+        });
     }
 
-    fn dither_helper(
-        self,
-        value: f64,
-        color_map: &Vec<ColorMapElement>,
-        x: usize,
-        y: usize,
-    ) -> RGB {
-        let mut index = 0;
-        while index < color_map.len() {
-            if value < self.get_threshold(x, y) * color_map[index].scale + color_map[index].offset {
-                return color_map[index].color;
-            }
-            index += 1;
-        }
-        return color_map.last().unwrap().color;
+    // the threshold of every pixel of an image of the given size, in row order
+    pub fn threshold_map(self, width: u32, height: u32, options: &DitherOptions) -> Vec<f64> {
+        let mut noise = options.noise_source();
+        let tile = options.tileable.then_some((width as usize, height as usize));
+        (0..(width * height) as usize)
+            .map(|index| self.get_threshold(index % width as usize, index / width as usize, &mut noise, tile))
+            .collect()
     }
 
-    fn get_threshold(self, x: usize, y: usize) -> f64 {
+    // with a tile size, the pattern repeats exactly over the tile: matrices are stretched to fit
+    // a whole number of times and the steps of the procedural sequences are rounded to a whole
+    // number of turns. the golden ratio sequence and random thresholds have no period to align
+    fn get_threshold(self, x: usize, y: usize, noise: &mut NoiseSource, tile: Option<(usize, usize)>) -> f64 {
+        let (x, y) = match (tile, self.period()) {
+            (Some((width, height)), Some(period)) => (align(x, width, period), align(y, height, period)),
+            _ => (x, y),
+        };
+        let (tile_width, tile_height) = (tile.map(|tile| tile.0), tile.map(|tile| tile.1));
+
         match self {
-            ThresholdType::Rand => rand::rng().random::<f64>(),
+            ThresholdType::Rand => noise.next(x, y),
             ThresholdType::Bayer0 => 1.0 - BAYER0[y % 2 * 2 + x % 2],
             ThresholdType::Bayer1 => 1.0 - BAYER1[y % 4 * 4 + x % 4],
             ThresholdType::Bayer2 => 1.0 - BAYER2[y % 8 * 8 + x % 8],
             ThresholdType::Bayer3 => 1.0 - BAYER3[y % 16 * 16 + x % 16],
             ThresholdType::BlueNoise => BLUE_NOISE[y % 128 * 128 + x % 128],
+            // Jimenez, "Next Generation Post Processing in Call of Duty: Advanced Warfare"
+            ThresholdType::InterleavedGradientNoise => {
+                let (step_x, step_y) = (turns(0.067_110_56, tile_width), turns(0.005_837_15, tile_height));
+                (52.982_918_9 * (step_x * x as f64 + step_y * y as f64).fract()).fract()
+            }
+            // Roberts, "The Unreasonable Effectiveness of Quasirandom Sequences"
+            ThresholdType::R2 => {
+                let (step_x, step_y) = (turns(1.0 / PLASTIC, tile_width), turns(1.0 / (PLASTIC * PLASTIC), tile_height));
+                (0.5 + x as f64 * step_x + y as f64 * step_y).fract()
+            }
+            // the 1D golden ratio sequence, visiting pixels along a Z-order curve so neighbours in
+            // the image stay close in the sequence (a plane like x/phi + y/phi^2 collapses to lines)
+            ThresholdType::GoldenRatio => (0.5 + morton_index(x, y) as f64 / GOLDEN).fract(),
+            ThresholdType::HashNoise => noise.position_value(x, y),
+        }
+    }
+
+    // size of the threshold matrix
+    fn period(self) -> Option<usize> {
+        match self {
+            ThresholdType::Bayer0 => Some(2),
+            ThresholdType::Bayer1 => Some(4),
+            ThresholdType::Bayer2 => Some(8),
+            ThresholdType::Bayer3 => Some(16),
+            ThresholdType::BlueNoise => Some(128),
+            _ => None,
+        }
+    }
+}
+
+// the position in a pattern stretched or squeezed to repeat a whole number of times over the size,
+// sampled at the center of the pixels so the first and last pixels fall on both ends of the pattern
+fn align(position: usize, size: usize, period: usize) -> usize {
+    if size.is_multiple_of(period) {
+        return position;
+    }
+    let length = ((size as f64 / period as f64).round() as usize).max(1) * period;
+    (2 * position + 1) * length / (2 * size)
+}
+
+// the step closest to the given one going a whole number of times around over the size
+fn turns(step: f64, size: Option<usize>) -> f64 {
+    match size {
+        Some(size) => (step * size as f64).round().max(1.0) / size as f64,
+        None => step,
+    }
+}
+
+// quantizes every pixel against its own threshold, given by (x, y, luminance) -> threshold
+pub fn dither_with_thresholds(
+    data: &mut [RGB],
+    width: u32,
+    color_map: &[ColorMapElement],
+    mut threshold: impl FnMut(usize, usize, f64) -> f64,
+) {
+    let mut index = 0;
+    while index < data.len() {
+        let value = data[index].grayscale();
+        let threshold = threshold(index % width as usize, index / width as usize, value);
+        if let Some(color) = dither_helper(value, threshold, color_map) {
+            data[index] = color;
+        }
+
+        index += 1;
+    }
+}
+
+// the first color of the color map whose threshold is above the value, None for an empty map
+pub fn dither_helper(value: f64, threshold: f64, color_map: &[ColorMapElement]) -> Option<RGB> {
+    let mut index = 0;
+    while index < color_map.len() {
+        if value < threshold * color_map[index].scale + color_map[index].offset {
+            return Some(color_map[index].color);
         }
+        index += 1;
+    }
+    color_map.last().map(|element| element.color)
+}
+
+// interleaves the bits of x and y, the position of the pixel along a Z-order curve
+fn morton_index(x: usize, y: usize) -> u64 {
+    let spread = |value: usize| {
+        let mut value = value as u64 & 0xFFFF_FFFF;
+        value = (value | (value << 16)) & 0x0000_FFFF_0000_FFFF;
+        value = (value | (value << 8)) & 0x00FF_00FF_00FF_00FF;
+        value = (value | (value << 4)) & 0x0F0F_0F0F_0F0F_0F0F;
+        value = (value | (value << 2)) & 0x3333_3333_3333_3333;
+        (value | (value << 1)) & 0x5555_5555_5555_5555
+    };
+    spread(x) | (spread(y) << 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tileable_patterns_wrap() {
+        for size in [3, 10, 13, 20, 127, 200] {
+            for period in [2, 4, 8, 128].into_iter().filter(|period| *period <= size) {
+                // the seam goes from the end of the pattern back to its start
+                assert_eq!(align(0, size, period) % period, 0);
+                assert_eq!(align(size - 1, size, period) % period, period - 1, "{} over {}", period, size);
+            }
+            assert!(((turns(1.0 / PLASTIC, Some(size)) * size as f64).fract()).abs() < 1e-9);
+        }
+        assert_eq!(align(37, 64, 8), 37);
+
+        let options = DitherOptions { tileable: true, ..Default::default() };
+        let (width, height) = (10, 6);
+        let map = ThresholdType::Bayer1.threshold_map(width, height, &options);
+        // neighbours across the seam are neighbours in the matrix
+        assert_eq!(map[9], 1.0 - BAYER1[3]);
+        assert_eq!(map[0], 1.0 - BAYER1[0]);
     }
 }
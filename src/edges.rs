@@ -0,0 +1,241 @@
+use crate::{image_utils::gaussian_blur, pixel_util::RGB};
+
+// ratio between the two gaussians of the difference of gaussians, close to a laplacian of gaussian
+const DOG_RATIO: f64 = 1.6;
+// canny keeps weak edges above this fraction of the threshold when they touch a strong one
+const CANNY_LOW_RATIO: f64 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum EdgeDetector {
+    // gradient magnitude, thick and noisy but cheap
+    #[default]
+    Sobel,
+    // thin gradient ridges, connected with hysteresis
+    Canny,
+    // dark side of the edges, like ink lines
+    DifferenceOfGaussians,
+}
+
+impl EdgeDetector {
+    pub const ALL: [EdgeDetector; 3] = [Self::Sobel, Self::Canny, Self::DifferenceOfGaussians];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Sobel => "sobel",
+            Self::Canny => "canny",
+            Self::DifferenceOfGaussians => "dog",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<EdgeDetector> {
+        Self::ALL.into_iter().find(|detector| detector.name() == name)
+    }
+}
+
+// settings of the outline layer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EdgeOptions {
+    pub detector: EdgeDetector,
+    // strength an edge needs to be drawn, from 0.0 to 1.0
+    pub threshold: f64,
+    // width of the lines in processing pixels
+    pub thickness: u32,
+    // blur before detection, in processing pixels (the smaller gaussian for dog)
+    pub sigma: f64,
+    // color of the lines, the darkest color of the color map when not given
+    pub color: Option<RGB>,
+    // only the lines over a transparent background, for compositing
+    pub only: bool,
+}
+
+impl Default for EdgeOptions {
+    fn default() -> Self {
+        EdgeOptions {
+            detector: EdgeDetector::default(),
+            threshold: 0.25,
+            thickness: 1,
+            sigma: 1.0,
+            color: None,
+            only: false,
+        }
+    }
+}
+
+// the pixels on an edge of the luminance, with lines grown to the wanted thickness
+pub fn detect(luminance: &[f64], width: u32, height: u32, options: &EdgeOptions) -> Vec<bool> {
+    let edges = match options.detector {
+        EdgeDetector::Sobel => {
+            let blurred = gaussian_blur(luminance, width, height, options.sigma);
+            sobel(&blurred, width, height)
+                .iter()
+                .map(|(magnitude, _)| *magnitude > options.threshold)
+                .collect()
+        }
+        EdgeDetector::Canny => canny(luminance, width, height, options),
+        EdgeDetector::DifferenceOfGaussians => {
+            let small = gaussian_blur(luminance, width, height, options.sigma);
+            let large = gaussian_blur(luminance, width, height, options.sigma * DOG_RATIO);
+            // the difference is much weaker than a gradient, scaled to use similar thresholds
+            small
+                .iter()
+                .zip(large.iter())
+                .map(|(s, l)| (l - s) * 4.0 > options.threshold)
+                .collect()
+        }
+    };
+
+    thicken(&edges, width, height, options.thickness)
+}
+
+// draws the lines over the dithered pixels, or alone over a transparent background
+pub fn draw(data: &mut [RGB], edges: &[bool], color: RGB, only: bool) {
+    for (pixel, edge) in data.iter_mut().zip(edges) {
+        if *edge {
+            *pixel = color;
+        } else if only {
+            *pixel = RGB { r: 0.0, g: 0.0, b: 0.0, a: 0.0 };
+        }
+    }
+}
+
+// gradient magnitude (1.0 for a step from black to white) and direction of every pixel
+fn sobel(values: &[f64], width: u32, height: u32) -> Vec<(f64, f64)> {
+    let (w, h) = (width as isize, height as isize);
+    let at = |x: isize, y: isize| values[(y.clamp(0, h - 1) * w + x.clamp(0, w - 1)) as usize];
+
+    (0..values.len() as isize)
+        .map(|index| {
+            let (x, y) = (index % w, index / w);
+            let gx = (at(x + 1, y - 1) + 2.0 * at(x + 1, y) + at(x + 1, y + 1))
+                - (at(x - 1, y - 1) + 2.0 * at(x - 1, y) + at(x - 1, y + 1));
+            let gy = (at(x - 1, y + 1) + 2.0 * at(x, y + 1) + at(x + 1, y + 1))
+                - (at(x - 1, y - 1) + 2.0 * at(x, y - 1) + at(x + 1, y - 1));
+            ((gx * gx + gy * gy).sqrt() / 4.0, gy.atan2(gx))
+        })
+        .collect()
+}
+
+fn canny(luminance: &[f64], width: u32, height: u32, options: &EdgeOptions) -> Vec<bool> {
+    let (w, h) = (width as isize, height as isize);
+    let blurred = gaussian_blur(luminance, width, height, options.sigma);
+    let gradients = sobel(&blurred, width, height);
+    let magnitude = |x: isize, y: isize| -> f64 {
+        if x < 0 || y < 0 || x >= w || y >= h {
+            return 0.0;
+        }
+        gradients[(y * w + x) as usize].0
+    };
+
+    // non maximum suppression, only the ridge of the gradient across the edge is kept
+    let thin = (0..luminance.len() as isize)
+        .map(|index| {
+            let (x, y) = (index % w, index / w);
+            let (value, angle) = gradients[index as usize];
+            let step = (angle / (std::f64::consts::PI / 4.0)).round() as isize;
+            let (dx, dy) = match step.rem_euclid(4) {
+                0 => (1, 0),
+                1 => (1, 1),
+                2 => (0, 1),
+                _ => (-1, 1),
+            };
+            if value >= magnitude(x + dx, y + dy) && value >= magnitude(x - dx, y - dy) {
+                value
+            } else {
+                0.0
+            }
+        })
+        .collect::<Vec<f64>>();
+
+    // hysteresis, weak edges are kept when connected to strong ones
+    let low = options.threshold * CANNY_LOW_RATIO;
+    let mut edges = vec![false; thin.len()];
+    let mut stack = (0..thin.len())
+        .filter(|index| thin[*index] > options.threshold)
+        .collect::<Vec<usize>>();
+    while let Some(index) = stack.pop() {
+        if edges[index] {
+            continue;
+        }
+        edges[index] = true;
+
+        let (x, y) = (index as isize % w, index as isize / w);
+        for (dx, dy) in [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)] {
+            let (nx, ny) = (x + dx, y + dy);
+            if nx < 0 || ny < 0 || nx >= w || ny >= h {
+                continue;
+            }
+            let neighbour = (ny * w + nx) as usize;
+            if !edges[neighbour] && thin[neighbour] > low {
+                stack.push(neighbour);
+            }
+        }
+    }
+
+    edges
+}
+
+// grows the lines by a disk, a thickness of 1 leaves them as they are
+fn thicken(edges: &[bool], width: u32, height: u32, thickness: u32) -> Vec<bool> {
+    let radius = thickness.saturating_sub(1) as isize;
+    if radius == 0 {
+        return edges.to_vec();
+    }
+
+    let (w, h) = (width as isize, height as isize);
+    let mut thick = vec![false; edges.len()];
+    for index in (0..edges.len()).filter(|index| edges[*index]) {
+        let (x, y) = (index as isize % w, index as isize / w);
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                let (nx, ny) = (x + dx, y + dy);
+                if dx * dx + dy * dy <= radius * radius && nx >= 0 && ny >= 0 && nx < w && ny < h {
+                    thick[(ny * w + nx) as usize] = true;
+                }
+            }
+        }
+    }
+    thick
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detectors_find_a_step() {
+        let (width, height) = (32, 16);
+        // black on the left half, white on the right half
+        let luminance = (0..width * height)
+            .map(|index| if index % width < width / 2 { 0.0 } else { 1.0 })
+            .collect::<Vec<f64>>();
+
+        for detector in EdgeDetector::ALL {
+            let options = EdgeOptions {
+                detector,
+                ..Default::default()
+            };
+            let edges = detect(&luminance, width as u32, height as u32, &options);
+
+            let columns = (0..width)
+                .filter(|x| (0..height).any(|y| edges[y * width + x]))
+                .collect::<Vec<usize>>();
+            assert!(!columns.is_empty(), "{} found nothing", detector.name());
+            assert!(
+                columns.iter().all(|x| x.abs_diff(width / 2) <= 3),
+                "{} found edges in columns {:?}",
+                detector.name(),
+                columns
+            );
+        }
+    }
+
+    #[test]
+    fn test_thickness() {
+        let mut edges = vec![false; 9 * 9];
+        edges[4 * 9 + 4] = true;
+
+        assert_eq!(thicken(&edges, 9, 9, 1).iter().filter(|e| **e).count(), 1);
+        assert_eq!(thicken(&edges, 9, 9, 2).iter().filter(|e| **e).count(), 5);
+        assert_eq!(thicken(&edges, 9, 9, 3).iter().filter(|e| **e).count(), 13);
+    }
+}
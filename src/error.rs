@@ -0,0 +1,197 @@
+use std::{error::Error, fmt, io};
+
+use image::ImageError;
+
+// everything that can go wrong in the library, none of it panics
+#[derive(Debug)]
+pub enum DitherError {
+    // reading or writing a file
+    Io(io::Error),
+    // the input isn't an image that can be read
+    Decode(ImageError),
+    // the output couldn't be written in the requested format
+    Encode(ImageError),
+    // a setting that is missing or wrong, field is its path in the config, e.g. "mask.path"
+    Config { field: String, reason: String },
+    // a color or a color map that can't be used
+    Palette(String),
+    // an image or a canvas of a size that can't be used
+    Dimensions { width: u32, height: u32, reason: String },
+}
+
+impl DitherError {
+    pub fn config(field: &str, reason: &str) -> DitherError {
+        DitherError::Config {
+            field: field.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+
+    pub fn dimensions(width: u32, height: u32, reason: &str) -> DitherError {
+        DitherError::Dimensions {
+            width,
+            height,
+            reason: reason.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for DitherError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DitherError::Io(error) => write!(f, "io error: {}", error),
+            DitherError::Decode(error) => write!(f, "couldn't decode the image: {}", error),
+            DitherError::Encode(error) => write!(f, "couldn't encode the image: {}", error),
+            DitherError::Config { field, reason } if field.is_empty() => write!(f, "invalid config: {}", reason),
+            DitherError::Config { field, reason } => write!(f, "invalid config, {}: {}", field, reason),
+            DitherError::Palette(reason) => write!(f, "invalid palette: {}", reason),
+            DitherError::Dimensions { width, height, reason } => {
+                write!(f, "invalid size {}x{}: {}", width, height, reason)
+            }
+        }
+    }
+}
+
+impl Error for DitherError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DitherError::Io(error) => Some(error),
+            DitherError::Decode(error) | DitherError::Encode(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for DitherError {
+    fn from(error: io::Error) -> Self {
+        DitherError::Io(error)
+    }
+}
+
+// errors met while reading, writing goes through DitherError::Encode
+impl From<ImageError> for DitherError {
+    fn from(error: ImageError) -> Self {
+        match error {
+            ImageError::IoError(error) => DitherError::Io(error),
+            ImageError::Encoding(_) => DitherError::Encode(error),
+            error => DitherError::Decode(error),
+        }
+    }
+}
+
+// the json of a config that isn't json
+impl From<json::Error> for DitherError {
+    fn from(error: json::Error) -> Self {
+        DitherError::config("", &error.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        alpha::AlphaMode,
+        config::ProcessConfig,
+        ditherer::Ditherer,
+        dithering::{
+            dbs::{self, DbsOptions},
+            importance::Importance,
+            mask::{self, Side},
+            DitherOptions, DitheringType,
+        },
+        edges::EdgeOptions,
+        filters::Filter,
+        font, image_utils,
+        pixel_util::RGB,
+        resize::{self, FitMode, Pad},
+        stipple::StippleOptions,
+    };
+    use image::DynamicImage;
+
+    #[test]
+    fn test_bad_input_is_an_error() {
+        // too short, or cut in the middle of a character
+        for hex in ["", "#fff", "12345", "ééé", "zz0000"] {
+            assert!(matches!(RGB::from_hex(hex.to_string()), Err(DitherError::Palette(_))), "{}", hex);
+        }
+        assert!(matches!(
+            image_utils::rgb_to_dynimg(&[RGB::from_u8(0, 0, 0, 255)], 2, 2),
+            Err(DitherError::Dimensions { width: 2, height: 2, .. })
+        ));
+
+        let missing = "/nonexistent/file".to_string();
+        assert!(matches!(ProcessConfig::read_config(&missing), Err(DitherError::Io(_))));
+        assert!(matches!(image_utils::read_image(&missing), Err(DitherError::Io(_))));
+        let image = DynamicImage::new_rgba8(2, 2);
+        assert!(matches!(
+            image_utils::write_image(&image, &missing, image::ImageFormat::Png),
+            Err(DitherError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn test_sizes_are_bounded() {
+        let dimensions = |result: Result<_, DitherError>| matches!(result, Err(DitherError::Dimensions { .. }));
+        assert!(dimensions(Ditherer::new().scale(1e9).build().map(|_| ())));
+        assert!(dimensions(Ditherer::new().resize(100000, 100000).build().map(|_| ())));
+        let config = Ditherer::new().resize(8, 8).build().unwrap().config().clone();
+        let canvas = ProcessConfig {
+            pad: Some(Pad { width: 100000, height: 100000, color: None }),
+            ..config.clone()
+        };
+        assert!(dimensions(canvas.validate()));
+
+        // fitting the width of a very thin image goes far past the processing height
+        let thin = DynamicImage::new_rgba8(1, 10000);
+        let fitted = ProcessConfig { processing_width: 8000, fit: FitMode::Width, ..config.clone() };
+        assert!(dimensions(crate::process(&fitted, &thin).map(|_| ())));
+        assert!(crate::process(&config, &thin).is_ok());
+    }
+
+    #[test]
+    fn test_public_functions_dont_panic() {
+        let black = RGB::from_u8(0, 0, 0, 255);
+        assert!(resize::pad(&[black; 3], 2, 2, &Pad { width: 4, height: 4, color: None }, black).is_err());
+        assert_eq!(font::wrap_text("some words", 0, 30), vec!["some", "words"]);
+
+        // an empty color map leaves the pixels as they are
+        let data = [RGB::from_u8(128, 128, 128, 255); 4];
+        let empty = Side { data: &data, dithering_type: &DitheringType::Bayer0, color_map: &[] };
+        let options = DitherOptions::default();
+        assert_eq!(mask::dither(&empty, &empty, &[0.5; 4], 2, 2, true, &options), data.to_vec());
+        let mut pixels = data;
+        Importance::default().dither(&mut pixels, &[0.5; 4], 2, 2, &DitheringType::Bayer0, &[], &options);
+        assert_eq!(pixels, data);
+        let mut pixels = data;
+        Importance::default().dither(&mut pixels, &[0.5; 3], 2, 2, &DitheringType::Bayer0, &[], &options);
+        assert_eq!(pixels, data);
+        let mut pixels = data;
+        dbs::refine(&mut pixels, &[0.5; 4], 2, 2, &[], &DbsOptions::default(), |_| {});
+        assert_eq!(pixels, data);
+        let map = Ditherer::new().build().unwrap().config().color_map.clone();
+        dbs::refine(&mut pixels, &[0.5; 3], 2, 2, &map, &DbsOptions::default(), |_| {});
+        assert_eq!(pixels, data);
+
+        // the library entry points check the config like process does
+        let config = Ditherer::new().resize(2, 2).build().unwrap().config().clone();
+        let image = DynamicImage::new_rgba8(2, 2);
+        let colorless = ProcessConfig { color_map: Vec::new(), alpha: Some(AlphaMode::Background(None)), ..config.clone() };
+        assert!(matches!(crate::preprocess(&colorless, &image), Err(DitherError::Palette(_))));
+        assert!(matches!(crate::dither(&colorless, &image), Err(DitherError::Palette(_))));
+
+        // blurs and dots that couldn't be allocated
+        let huge = 1e15;
+        let configs = [
+            ("filters", ProcessConfig { filters: vec![Filter::Blur { sigma: huge }], ..config.clone() }),
+            ("filters", ProcessConfig { filters: vec![Filter::UnsharpMask { sigma: huge, amount: 1.0 }], ..config.clone() }),
+            ("edges.sigma", ProcessConfig { edges: Some(EdgeOptions { sigma: huge, ..Default::default() }), ..config.clone() }),
+            ("importance.blur", ProcessConfig { importance: Some(Importance { blur: huge, ..Default::default() }), ..config.clone() }),
+            ("dbs.filter_width", ProcessConfig { dbs: Some(DbsOptions { filter_width: huge, ..Default::default() }), ..config.clone() }),
+            ("stipple.points", ProcessConfig { stipple: Some(StippleOptions { points: 100_000_000_000, ..Default::default() }), ..config.clone() }),
+        ];
+        for (field, config) in configs {
+            assert!(matches!(crate::dither(&config, &image), Err(DitherError::Config { field: f, .. }) if f == field), "{}", field);
+        }
+        assert!(image_utils::gaussian_blur(&[0.5; 4], 2, 2, huge).iter().all(|value| (value - 0.5).abs() < 1e-9));
+    }
+}
@@ -0,0 +1,420 @@
+use image::{DynamicImage, Rgba, RgbaImage};
+
+use crate::{
+    image_utils::{dynimg_to_rgb, gaussian_blur},
+    pixel_util::RGB,
+};
+
+const HISTOGRAM_BINS: usize = 256;
+
+// one step of the chain run before dithering, values are from 0.0 to 1.0
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    // above 1.0 lightens the mid-tones
+    Gamma { gamma: f64 },
+    // black and white points, everything outside is clipped
+    Levels { black: f64, white: f64 },
+    // levels with the black and white points at the low and high quantiles of the luminance
+    AutoLevels { low: f64, high: f64 },
+    // smooth curve through (input, output) points, sorted by input
+    Curves { points: Vec<(f64, f64)> },
+    UnsharpMask { sigma: f64, amount: f64 },
+    Blur { sigma: f64 },
+    // spreads the luminance over the whole range
+    Equalize,
+    // equalization by tiles, with the contrast gain limited to clip_limit
+    Clahe { tiles: u32, clip_limit: f64 },
+    Posterize { levels: u32 },
+    Invert,
+    // 0.0 is grey, 1.0 leaves the colors as they are
+    Saturation { amount: f64 },
+}
+
+impl Filter {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Filter::Gamma { .. } => "gamma",
+            Filter::Levels { .. } => "levels",
+            Filter::AutoLevels { .. } => "auto_levels",
+            Filter::Curves { .. } => "curves",
+            Filter::UnsharpMask { .. } => "unsharp_mask",
+            Filter::Blur { .. } => "blur",
+            Filter::Equalize => "equalize",
+            Filter::Clahe { .. } => "clahe",
+            Filter::Posterize { .. } => "posterize",
+            Filter::Invert => "invert",
+            Filter::Saturation { .. } => "saturation",
+        }
+    }
+
+    pub fn apply(&self, data: &mut [RGB], width: u32, height: u32) {
+        match self {
+            Filter::Gamma { gamma } => map_channels(data, |c| c.max(0.0).powf(1.0 / gamma)),
+            Filter::Levels { black, white } => {
+                let range = (white - black).max(f64::EPSILON);
+                map_channels(data, |c| ((c - black) / range).clamp(0.0, 1.0))
+            }
+            Filter::AutoLevels { low, high } => {
+                let mut luminance = data.iter().map(|pixel| pixel.grayscale()).collect::<Vec<f64>>();
+                luminance.sort_by(|a, b| a.total_cmp(b));
+                let quantile = |q: f64| {
+                    luminance[(q.clamp(0.0, 1.0) * (luminance.len() - 1) as f64).round() as usize]
+                };
+                let (black, white) = (quantile(*low), quantile(*high));
+                // a flat image has nothing to stretch
+                if white - black > 1.0 / 255.0 {
+                    Filter::Levels { black, white }.apply(data, width, height);
+                }
+            }
+            Filter::Curves { points } => {
+                let curve = MonotoneCurve::new(points);
+                map_channels(data, |c| curve.evaluate(c))
+            }
+            Filter::UnsharpMask { sigma, amount } => {
+                let blurred = blur_channels(data, width, height, *sigma);
+                for (pixel, blur) in data.iter_mut().zip(blurred) {
+                    pixel.r += amount * (pixel.r - blur.r);
+                    pixel.g += amount * (pixel.g - blur.g);
+                    pixel.b += amount * (pixel.b - blur.b);
+                }
+            }
+            Filter::Blur { sigma } => {
+                let blurred = blur_channels(data, width, height, *sigma);
+                data.copy_from_slice(&blurred);
+            }
+            Filter::Equalize => {
+                let luminance = data.iter().map(|pixel| pixel.grayscale()).collect::<Vec<f64>>();
+                let mapping = equalization(&luminance, 0.0);
+                shift_luminance(data, |_, l| mapping[bin(l)]);
+            }
+            Filter::Clahe { tiles, clip_limit } => {
+                let equalized = clahe(data, width, height, *tiles, *clip_limit);
+                shift_luminance(data, |index, _| equalized[index]);
+            }
+            Filter::Posterize { levels } => {
+                let steps = ((*levels).max(2) - 1) as f64;
+                map_channels(data, |c| (c.clamp(0.0, 1.0) * steps).round() / steps)
+            }
+            Filter::Invert => map_channels(data, |c| 1.0 - c),
+            Filter::Saturation { amount } => {
+                for pixel in data.iter_mut() {
+                    let l = pixel.grayscale();
+                    pixel.r = l + (pixel.r - l) * amount;
+                    pixel.g = l + (pixel.g - l) * amount;
+                    pixel.b = l + (pixel.b - l) * amount;
+                }
+            }
+        }
+
+        for pixel in data.iter_mut() {
+            pixel.r = pixel.r.clamp(0.0, 1.0);
+            pixel.g = pixel.g.clamp(0.0, 1.0);
+            pixel.b = pixel.b.clamp(0.0, 1.0);
+        }
+    }
+}
+
+// automatic tone preparation, so that images of all kinds dither alike without tuning
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AutoTone {
+    // fraction of the darkest and of the lightest pixels clipped by the auto levels
+    pub clip: f64,
+    // clahe tiles per side, 0 for none
+    pub tiles: u32,
+    pub clip_limit: f64,
+}
+
+impl Default for AutoTone {
+    fn default() -> Self {
+        AutoTone {
+            clip: 0.01,
+            tiles: 8,
+            clip_limit: 2.0,
+        }
+    }
+}
+
+impl AutoTone {
+    pub fn filters(&self) -> Vec<Filter> {
+        let mut filters = vec![Filter::AutoLevels {
+            low: self.clip,
+            high: 1.0 - self.clip,
+        }];
+        if self.tiles > 0 {
+            filters.push(Filter::Clahe {
+                tiles: self.tiles,
+                clip_limit: self.clip_limit,
+            });
+        }
+        filters
+    }
+}
+
+// runs the filters one after another, the image is left untouched without any
+pub fn apply_all(filters: &[Filter], image: DynamicImage) -> DynamicImage {
+    if filters.is_empty() {
+        return image;
+    }
+
+    let (width, height) = (image.width(), image.height());
+    let mut data = dynimg_to_rgb(&image);
+    for filter in filters {
+        filter.apply(&mut data, width, height);
+    }
+
+    DynamicImage::ImageRgba8(RgbaImage::from_fn(width, height, |x, y| {
+        let pixel = data[(y * width + x) as usize];
+        Rgba([pixel.r, pixel.g, pixel.b, pixel.a].map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8))
+    }))
+}
+
+fn map_channels(data: &mut [RGB], f: impl Fn(f64) -> f64) {
+    for pixel in data.iter_mut() {
+        pixel.r = f(pixel.r);
+        pixel.g = f(pixel.g);
+        pixel.b = f(pixel.b);
+    }
+}
+
+fn blur_channels(data: &[RGB], width: u32, height: u32, sigma: f64) -> Vec<RGB> {
+    let channel = |f: fn(&RGB) -> f64| {
+        gaussian_blur(&data.iter().map(f).collect::<Vec<f64>>(), width, height, sigma)
+    };
+    let (r, g, b) = (channel(|p| p.r), channel(|p| p.g), channel(|p| p.b));
+
+    data.iter()
+        .enumerate()
+        .map(|(index, pixel)| RGB {
+            r: r[index],
+            g: g[index],
+            b: b[index],
+            a: pixel.a,
+        })
+        .collect()
+}
+
+// moves every channel by the change of luminance, (index, luminance) -> new luminance
+fn shift_luminance(data: &mut [RGB], new_luminance: impl Fn(usize, f64) -> f64) {
+    for (index, pixel) in data.iter_mut().enumerate() {
+        let l = pixel.grayscale();
+        let shift = new_luminance(index, l) - l;
+        pixel.r += shift;
+        pixel.g += shift;
+        pixel.b += shift;
+    }
+}
+
+fn bin(value: f64) -> usize {
+    ((value.clamp(0.0, 1.0) * (HISTOGRAM_BINS - 1) as f64).round() as usize).min(HISTOGRAM_BINS - 1)
+}
+
+// the cumulative histogram of the values as a mapping of every bin to its new value, bins above
+// `clip` times the average are cut down and the excess shared between all bins (no clip with 0.0)
+fn equalization(values: &[f64], clip: f64) -> Vec<f64> {
+    let mut histogram = vec![0.0; HISTOGRAM_BINS];
+    for value in values {
+        histogram[bin(*value)] += 1.0;
+    }
+
+    if clip > 0.0 {
+        let limit = (clip * values.len() as f64 / HISTOGRAM_BINS as f64).max(1.0);
+        let excess: f64 = histogram.iter().map(|count| (count - limit).max(0.0)).sum();
+        for count in histogram.iter_mut() {
+            *count = count.min(limit) + excess / HISTOGRAM_BINS as f64;
+        }
+    }
+
+    let total: f64 = histogram.iter().sum::<f64>().max(f64::EPSILON);
+    let mut sum = 0.0;
+    histogram
+        .iter()
+        .map(|count| {
+            sum += count;
+            sum / total
+        })
+        .collect()
+}
+
+// contrast limited adaptive histogram equalization of the luminance: every tile gets its own
+// equalization, pixels blend the mappings of the four closest tiles
+fn clahe(data: &[RGB], width: u32, height: u32, tiles: u32, clip_limit: f64) -> Vec<f64> {
+    let (w, h) = (width as usize, height as usize);
+    let tiles = tiles.max(1) as usize;
+    let (tile_width, tile_height) = (w.div_ceil(tiles).max(1), h.div_ceil(tiles).max(1));
+    let (columns, rows) = (w.div_ceil(tile_width), h.div_ceil(tile_height));
+    let luminance = data.iter().map(|pixel| pixel.grayscale()).collect::<Vec<f64>>();
+
+    let mappings = (0..columns * rows)
+        .map(|tile| {
+            let (tx, ty) = (tile % columns * tile_width, tile / columns * tile_height);
+            let values = (ty..(ty + tile_height).min(h))
+                .flat_map(|y| (tx..(tx + tile_width).min(w)).map(move |x| (x, y)))
+                .map(|(x, y)| luminance[y * w + x])
+                .collect::<Vec<f64>>();
+            equalization(&values, clip_limit)
+        })
+        .collect::<Vec<Vec<f64>>>();
+
+    (0..w * h)
+        .map(|index| {
+            let (x, y) = (index % w, index / w);
+            // position among the tile centers
+            let fx = ((x as f64 + 0.5) / tile_width as f64 - 0.5).clamp(0.0, (columns - 1) as f64);
+            let fy = ((y as f64 + 0.5) / tile_height as f64 - 0.5).clamp(0.0, (rows - 1) as f64);
+            let (x0, y0) = (fx.floor() as usize, fy.floor() as usize);
+            let (x1, y1) = ((x0 + 1).min(columns - 1), (y0 + 1).min(rows - 1));
+            let (wx, wy) = (fx - x0 as f64, fy - y0 as f64);
+
+            let b = bin(luminance[index]);
+            let top = mappings[y0 * columns + x0][b] * (1.0 - wx) + mappings[y0 * columns + x1][b] * wx;
+            let bottom = mappings[y1 * columns + x0][b] * (1.0 - wx) + mappings[y1 * columns + x1][b] * wx;
+            top * (1.0 - wy) + bottom * wy
+        })
+        .collect()
+}
+
+// monotone cubic interpolation (Fritsch-Carlson), smooth without overshooting the points
+struct MonotoneCurve {
+    points: Vec<(f64, f64)>,
+    tangents: Vec<f64>,
+}
+
+impl MonotoneCurve {
+    fn new(points: &[(f64, f64)]) -> MonotoneCurve {
+        let mut points = points.to_vec();
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        points.dedup_by(|a, b| a.0 == b.0);
+
+        let n = points.len();
+        let slopes = points
+            .windows(2)
+            .map(|pair| (pair[1].1 - pair[0].1) / (pair[1].0 - pair[0].0))
+            .collect::<Vec<f64>>();
+        let mut tangents = (0..n)
+            .map(|i| match i {
+                _ if n < 2 => 0.0,
+                0 => slopes[0],
+                _ if i == n - 1 => slopes[n - 2],
+                _ if slopes[i - 1] * slopes[i] <= 0.0 => 0.0,
+                _ => (slopes[i - 1] + slopes[i]) / 2.0,
+            })
+            .collect::<Vec<f64>>();
+
+        for (i, slope) in slopes.iter().enumerate() {
+            if *slope == 0.0 {
+                tangents[i] = 0.0;
+                tangents[i + 1] = 0.0;
+                continue;
+            }
+            let (a, b) = (tangents[i] / slope, tangents[i + 1] / slope);
+            let length = (a * a + b * b).sqrt();
+            if length > 3.0 {
+                tangents[i] = 3.0 * a / length * slope;
+                tangents[i + 1] = 3.0 * b / length * slope;
+            }
+        }
+
+        MonotoneCurve { points, tangents }
+    }
+
+    fn evaluate(&self, x: f64) -> f64 {
+        let points = &self.points;
+        match points.len() {
+            0 => return x,
+            1 => return points[0].1,
+            _ => {}
+        }
+        // nothing to place on the curve, the value goes through like an empty curve
+        if x.is_nan() {
+            return x;
+        }
+        if x <= points[0].0 {
+            return points[0].1;
+        }
+        if x >= points[points.len() - 1].0 {
+            return points[points.len() - 1].1;
+        }
+
+        let i = points.windows(2).position(|pair| x < pair[1].0).unwrap_or(points.len() - 2);
+        let ((x0, y0), (x1, y1)) = (points[i], points[i + 1]);
+        let step = x1 - x0;
+        let t = (x - x0) / step;
+        let (t2, t3) = (t * t, t * t * t);
+
+        (2.0 * t3 - 3.0 * t2 + 1.0) * y0
+            + (t3 - 2.0 * t2 + t) * step * self.tangents[i]
+            + (-2.0 * t3 + 3.0 * t2) * y1
+            + (t3 - t2) * step * self.tangents[i + 1]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grey(value: f64) -> RGB {
+        RGB { r: value, g: value, b: value, a: 1.0 }
+    }
+
+    fn run(filter: Filter, values: &[f64]) -> Vec<f64> {
+        let mut data = values.iter().map(|value| grey(*value)).collect::<Vec<RGB>>();
+        filter.apply(&mut data, values.len() as u32, 1);
+        data.iter().map(|pixel| pixel.r).collect()
+    }
+
+    #[test]
+    fn test_point_filters() {
+        let values = [0.0, 0.25, 0.5, 1.0];
+        let close = |a: Vec<f64>, b: [f64; 4]| a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-9);
+
+        assert!(close(run(Filter::Gamma { gamma: 0.5 }, &values), [0.0, 0.0625, 0.25, 1.0]));
+        assert!(close(run(Filter::Levels { black: 0.25, white: 0.75 }, &values), [0.0, 0.0, 0.5, 1.0]));
+        assert!(close(run(Filter::Posterize { levels: 3 }, &values), [0.0, 0.5, 0.5, 1.0]));
+        assert!(close(run(Filter::Invert, &values), [1.0, 0.75, 0.5, 0.0]));
+        assert!(close(run(Filter::Saturation { amount: 0.0 }, &values), values));
+    }
+
+    #[test]
+    fn test_auto_levels() {
+        // a dull ramp with a few outliers
+        let mut values = (0..100).map(|i| 0.3 + 0.3 * i as f64 / 99.0).collect::<Vec<f64>>();
+        values[0] = 0.0;
+        values[99] = 1.0;
+
+        let leveled = run(Filter::AutoLevels { low: 0.02, high: 0.98 }, &values);
+        assert_eq!(leveled[1], 0.0);
+        assert_eq!(leveled[98], 1.0);
+        assert!((leveled[50] - 0.5).abs() < 0.05);
+
+        // the same picture, darker, comes out the same
+        let darker = values.iter().map(|v| v * 0.5).collect::<Vec<f64>>();
+        let leveled_darker = run(Filter::AutoLevels { low: 0.02, high: 0.98 }, &darker);
+        assert!(leveled.iter().zip(leveled_darker).all(|(a, b)| (a - b).abs() < 1e-9));
+    }
+
+    #[test]
+    fn test_curves() {
+        let points = vec![(0.0, 0.0), (0.25, 0.1), (0.75, 0.9), (1.0, 1.0)];
+        let values = (0..=100).map(|i| i as f64 / 100.0).collect::<Vec<f64>>();
+        let curved = run(Filter::Curves { points: points.clone() }, &values);
+
+        // through the points, never going back down
+        for &(x, y) in &points {
+            assert!((curved[(x * 100.0) as usize] - y).abs() < 1e-9);
+        }
+        assert!(curved.windows(2).all(|pair| pair[1] >= pair[0]));
+        assert!(run(Filter::Curves { points }, &[f64::NAN])[0].is_nan());
+    }
+
+    #[test]
+    fn test_equalization_stretches_the_range() {
+        let values = (0..256).map(|i| 0.4 + 0.2 * i as f64 / 255.0).collect::<Vec<f64>>();
+
+        for filter in [Filter::Equalize, Filter::Clahe { tiles: 4, clip_limit: 40.0 }] {
+            let equalized = run(filter.clone(), &values);
+            let min = equalized.iter().cloned().fold(f64::MAX, f64::min);
+            let max = equalized.iter().cloned().fold(f64::MIN, f64::max);
+            assert!(max - min > 0.6, "{}: {} to {}", filter.name(), min, max);
+        }
+    }
+}
@@ -1,10 +1,11 @@
-use crate::pixel_util::RGB;
+use crate::{error::DitherError, pixel_util::RGB};
 use image::{
-    metadata::Cicp, ConvertColorOptions, DynamicImage, ImageBuffer, ImageFormat, ImageReader, Rgba,
+    metadata::Cicp, ConvertColorOptions, DynamicImage, ImageBuffer, ImageError, ImageFormat,
+    ImageReader, Rgba,
 };
 use std::fs::File;
 
-pub fn read_image(path: &String) -> Result<DynamicImage, Box<dyn std::error::Error>> {
+pub fn read_image(path: &String) -> Result<DynamicImage, DitherError> {
     let mut image = ImageReader::open(path)?.decode()?;
     image.convert_color_space(
         Cicp::SRGB_LINEAR,
@@ -18,9 +19,13 @@ pub fn write_image(
     image: &DynamicImage,
     path: &String,
     image_format: ImageFormat,
-) -> Result<(), Box<dyn std::error::Error>> {
-    image.write_to(&mut File::create(path).unwrap(), image_format)?;
-    Ok(())
+) -> Result<(), DitherError> {
+    image
+        .write_to(&mut File::create(path)?, image_format)
+        .map_err(|error| match error {
+            ImageError::IoError(error) => DitherError::Io(error),
+            error => DitherError::Encode(error),
+        })
 }
 
 pub fn dynimg_to_rgb(image: &DynamicImage) -> Vec<RGB> {
@@ -31,7 +36,7 @@ pub fn dynimg_to_rgb(image: &DynamicImage) -> Vec<RGB> {
         .collect::<Vec<RGB>>()
 }
 
-pub fn rgb_to_dynimg(rgbs: &Vec<RGB>, width: u32, height: u32) -> DynamicImage {
+pub fn rgb_to_dynimg(rgbs: &[RGB], width: u32, height: u32) -> Result<DynamicImage, DitherError> {
     let raw_data = rgbs
         .iter()
         .flat_map(|p| {
@@ -44,8 +49,73 @@ pub fn rgb_to_dynimg(rgbs: &Vec<RGB>, width: u32, height: u32) -> DynamicImage {
         })
         .collect::<Vec<u8>>();
 
-    DynamicImage::ImageRgba8(
-        ImageBuffer::<Rgba<u8>, Vec<u8>>::from_raw(width, height, raw_data)
-            .expect("Could construct an image"),
-    )
+    match ImageBuffer::<Rgba<u8>, Vec<u8>>::from_raw(width, height, raw_data) {
+        Some(buffer) => Ok(DynamicImage::ImageRgba8(buffer)),
+        None => Err(DitherError::dimensions(
+            width,
+            height,
+            &format!("doesn't match {} pixels", rgbs.len()),
+        )),
+    }
+}
+
+// the image repeated side by side, e.g. 2 x 2 to check the seams of a tileable texture
+pub fn tile(image: &DynamicImage, columns: u32, rows: u32) -> DynamicImage {
+    let source = image.to_rgba8();
+    let (width, height) = source.dimensions();
+    DynamicImage::ImageRgba8(ImageBuffer::from_fn(width * columns, height * rows, |x, y| {
+        *source.get_pixel(x % width, y % height)
+    }))
+}
+
+pub fn luminance(rgbs: &[RGB]) -> Vec<f64> {
+    rgbs.iter().map(|p| p.grayscale()).collect()
+}
+
+// separable gaussian blur over a single channel, pixels outside the image repeat the edge
+pub fn gaussian_blur(values: &[f64], width: u32, height: u32, sigma: f64) -> Vec<f64> {
+    if sigma <= 0.0 {
+        return values.to_vec();
+    }
+
+    // past the size of the image the kernel would only cover repeated edges
+    let kernel = gaussian_kernel(sigma.min(width.max(height) as f64));
+    let radius = (kernel.len() / 2) as isize;
+    let (width, height) = (width as isize, height as isize);
+
+    let mut horizontal = vec![0.0; values.len()];
+    for y in 0..height {
+        for x in 0..width {
+            let mut sum = 0.0;
+            for (k, weight) in kernel.iter().enumerate() {
+                let sx = (x + k as isize - radius).clamp(0, width - 1);
+                sum += values[(y * width + sx) as usize] * weight;
+            }
+            horizontal[(y * width + x) as usize] = sum;
+        }
+    }
+
+    let mut blurred = vec![0.0; values.len()];
+    for y in 0..height {
+        for x in 0..width {
+            let mut sum = 0.0;
+            for (k, weight) in kernel.iter().enumerate() {
+                let sy = (y + k as isize - radius).clamp(0, height - 1);
+                sum += horizontal[(sy * width + x) as usize] * weight;
+            }
+            blurred[(y * width + x) as usize] = sum;
+        }
+    }
+
+    blurred
+}
+
+// normalized 1D gaussian, 3 sigmas on each side
+pub fn gaussian_kernel(sigma: f64) -> Vec<f64> {
+    let radius = (sigma * 3.0).ceil() as isize;
+    let kernel = (-radius..=radius)
+        .map(|i| (-((i * i) as f64) / (2.0 * sigma * sigma)).exp())
+        .collect::<Vec<f64>>();
+    let sum: f64 = kernel.iter().sum();
+    kernel.iter().map(|k| k / sum).collect()
 }
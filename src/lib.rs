@@ -1,42 +1,455 @@
+use std::borrow::Cow;
+
 use image::{imageops::FilterType, DynamicImage};
 
-use crate::config::ProcessConfig;
+use crate::{
+    alpha::AlphaMode,
+    color_palette::ColorMapElement,
+    config::{ProcessConfig, MAX_PIXELS},
+    dithering::{
+        dbs::DbsProgress,
+        mask::{Mask, Side},
+    },
+    error::DitherError,
+    pixel_util::RGB,
+    resize::{FitMode, ResizeFilter},
+    stipple::Stippling,
+};
 
+pub mod alpha;
 pub mod color_palette;
+pub mod compare;
 pub mod config;
+pub mod ditherer;
 pub mod dithering;
+pub mod edges;
+pub mod error;
+pub mod filters;
+pub mod font;
 pub mod image_utils;
+pub mod metrics;
 pub mod pixel_util;
+pub mod resize;
+pub mod spectrum;
+pub mod spot;
+pub mod stipple;
+pub mod sweep;
 
+// the output image, dithered or stippled
 pub fn run(
     config: ProcessConfig,
     original_img: DynamicImage,
-) -> Result<DynamicImage, Box<dyn std::error::Error>> {
-    let image = original_img
-        .resize(
-            config.processing_width,
-            config.processing_height,
-            image::imageops::FilterType::Gaussian,
+) -> Result<DynamicImage, DitherError> {
+    if config.stipple.is_some() {
+        return Ok(stipple(&config, &original_img)?.render(config.output_scale));
+    }
+    let dithered = process(&config, &original_img)?;
+
+    upscale(
+        &config,
+        &dithered.pixels,
+        dithered.prepared.width(),
+        dithered.prepared.height(),
+    )
+}
+
+// dots instead of dithering, the darkest color of the color map over the lightest one
+pub fn stipple(config: &ProcessConfig, original_img: &DynamicImage) -> Result<Stippling, DitherError> {
+    let prepared = preprocess(config, original_img)?;
+    let luminance = image_utils::luminance(&image_utils::dynimg_to_rgb(&prepared));
+
+    Ok(Stippling {
+        width: prepared.width(),
+        height: prepared.height(),
+        stipples: stipple::stipple(
+            &luminance,
+            prepared.width(),
+            prepared.height(),
+            &config.stipple.unwrap_or_default(),
+            config.seed,
+        ),
+        color: darkest(&config.color_map),
+        background: lightest(&config.color_map),
+    })
+}
+
+// everything processing relies on, so a config built in code or an empty image can't panic
+fn check(config: &ProcessConfig, original_img: &DynamicImage) -> Result<(), DitherError> {
+    config.validate()?;
+    if original_img.width() == 0 || original_img.height() == 0 {
+        return Err(DitherError::dimensions(original_img.width(), original_img.height(), "the image is empty"));
+    }
+    // the size the image is resized to, before the crop of the fill mode
+    let (width, height) = match &config.crop {
+        Some(crop) => {
+            let (_, _, width, height) = crop.area(original_img.width(), original_img.height());
+            (width, height)
+        }
+        None => (original_img.width(), original_img.height()),
+    };
+    let (width, height) =
+        resize::target_size(width, height, config.processing_width, config.processing_height, config.fit);
+    if width as u64 * height as u64 > MAX_PIXELS {
+        return Err(DitherError::dimensions(
+            width,
+            height,
+            &format!("the resized image is larger than {} pixels", MAX_PIXELS),
+        ));
+    }
+    match config.fit {
+        FitMode::Fill => config.check_size(width.min(config.processing_width), height.min(config.processing_height)),
+        _ => config.check_size(width, height),
+    }
+}
+
+fn darkest(color_map: &[ColorMapElement]) -> RGB {
+    color_map
+        .iter()
+        .map(|element| element.color)
+        .min_by(|a, b| a.grayscale().total_cmp(&b.grayscale()))
+        .unwrap()
+}
+
+fn lightest(color_map: &[ColorMapElement]) -> RGB {
+    color_map
+        .iter()
+        .map(|element| element.color)
+        .max_by(|a, b| a.grayscale().total_cmp(&b.grayscale()))
+        .unwrap()
+}
+
+// the result of dithering at processing size, along with the image it was made from
+pub struct Dithered {
+    pub prepared: DynamicImage,
+    pub pixels: Vec<RGB>,
+}
+
+impl Dithered {
+    pub fn report(&self) -> metrics::Report {
+        metrics::report(
+            &image_utils::dynimg_to_rgb(&self.prepared),
+            &self.pixels,
+            self.prepared.width(),
+            self.prepared.height(),
         )
-        .grayscale()
-        .brighten(config.brigthness_delta)
-        .adjust_contrast(config.constrast_delta);
+    }
+}
 
-    let mut rgbs = image_utils::dynimg_to_rgb(&image);
+pub fn process(config: &ProcessConfig, original_img: &DynamicImage) -> Result<Dithered, DitherError> {
+    process_with_progress(config, original_img, |_| {})
+}
 
-    config.dithering_type.dither(
-        &mut rgbs,
-        image.width(),
-        image.height(),
-        &config.color_map,
-    );
+// the same as process, with news from the direct binary search which can take a while
+pub fn process_with_progress(
+    config: &ProcessConfig,
+    original_img: &DynamicImage,
+    progress: impl FnMut(&DbsProgress),
+) -> Result<Dithered, DitherError> {
+    check(config, original_img)?;
+    let original = Some((original_img.width(), original_img.height()));
+    let colored = prepare_colors(config, original_img);
+    let mut prepared = adjust_tones(config, &colored, original, config.brigthness_delta, config.constrast_delta);
+
+    let opaque = config.alpha.as_ref().and_then(|mode| {
+        let alpha = image_utils::dynimg_to_rgb(&prepared).iter().map(|pixel| pixel.a).collect::<Vec<f64>>();
+        alpha::opaque(
+            &alpha,
+            prepared.width(),
+            prepared.height(),
+            mode,
+            &config.dithering_type,
+            &config.dither_options(),
+        )
+    });
+    if let Some(opaque) = &opaque {
+        prepared = alpha::clear(&prepared, opaque, lightest(&config.color_map).grayscale());
+    }
+
+    let mut pixels = match &config.mask {
+        Some(mask) => dither_masked(config, mask, &prepared, &colored, original),
+        None => dither_image(config, &prepared, original, progress),
+    };
+
+    let (width, height) = (prepared.width(), prepared.height());
+    let reference = image_utils::luminance(&image_utils::dynimg_to_rgb(&prepared));
+    if !config.spot_colors.is_empty() {
+        let matched = spot::find(&config.spot_colors, &image_utils::dynimg_to_rgb(&colored));
+        spot::apply(
+            &mut pixels,
+            &reference,
+            &matched,
+            &config.spot_colors,
+            width,
+            height,
+            lightest(&config.color_map),
+            &config.dithering_type,
+            &config.dither_options(),
+        );
+    }
+    draw_edges(config, &mut pixels, &reference, width, height);
+    if let Some(opaque) = &opaque {
+        alpha::cut(&mut pixels, opaque);
+    }
 
-    let new_image = image_utils::rgb_to_dynimg(&rgbs, image.width(), image.height());
-    let new_image = new_image.resize(
-        new_image.width() * config.output_scale,
-        new_image.height() * config.output_scale,
-        FilterType::Nearest,
+    Ok(Dithered { prepared, pixels })
+}
+
+// everything done to the image before dithering
+pub fn preprocess(config: &ProcessConfig, original_img: &DynamicImage) -> Result<DynamicImage, DitherError> {
+    check(config, original_img)?;
+    let colored = prepare_colors(config, original_img);
+    let original = Some((original_img.width(), original_img.height()));
+    Ok(adjust_tones(config, &colored, original, config.brigthness_delta, config.constrast_delta))
+}
+
+// cropped, resized and filtered, still in color
+fn prepare_colors(config: &ProcessConfig, original_img: &DynamicImage) -> DynamicImage {
+    let mut cropped = match &config.crop {
+        Some(crop) => Cow::Owned(resize::crop(original_img, crop)),
+        None => Cow::Borrowed(original_img),
+    };
+    // before resizing, so the colors of transparent pixels don't bleed into the edges
+    if let Some(AlphaMode::Background(color)) = &config.alpha {
+        let color = color.unwrap_or_else(|| lightest(&config.color_map));
+        cropped = Cow::Owned(alpha::composite(&cropped, color));
+    }
+    let resized = resize::resize(
+        &cropped,
+        config.processing_width,
+        config.processing_height,
+        config.resize_filter,
+        config.fit,
     );
 
-    Ok(new_image)
+    let mut filters = config.auto_tone.map(|auto_tone| auto_tone.filters()).unwrap_or_default();
+    filters.extend(config.filters.iter().cloned());
+
+    filters::apply_all(&filters, resized)
+}
+
+// the grey image that is dithered, with its brightness, contrast and importance
+fn adjust_tones(
+    config: &ProcessConfig,
+    colored: &DynamicImage,
+    original: Option<(u32, u32)>,
+    brigthness_delta: i32,
+    constrast_delta: f32,
+) -> DynamicImage {
+    let prepared = colored
+        .grayscale()
+        .brighten(brigthness_delta)
+        .adjust_contrast(constrast_delta);
+
+    match &config.importance {
+        Some(importance) => {
+            let (width, height) = (prepared.width(), prepared.height());
+            let weights = map_weights(config, &importance.image, original, width, height);
+            let luminance = image_utils::luminance(&image_utils::dynimg_to_rgb(&prepared));
+            let adjusted = importance.adjust_tones(&luminance, &weights, width, height);
+
+            let mut pixels = prepared.to_rgba8();
+            for (pixel, value) in pixels.pixels_mut().zip(adjusted) {
+                let value = (value * 255.0).round() as u8;
+                pixel.0[..3].copy_from_slice(&[value; 3]);
+            }
+            DynamicImage::ImageRgba8(pixels)
+        }
+        None => prepared,
+    }
+}
+
+pub fn dither(config: &ProcessConfig, image: &DynamicImage) -> Result<Vec<RGB>, DitherError> {
+    dither_with_progress(config, image, |_| {})
+}
+
+// the dithering, its refinement and the outlines, the spot colors are added by process
+pub fn dither_with_progress(
+    config: &ProcessConfig,
+    image: &DynamicImage,
+    progress: impl FnMut(&DbsProgress),
+) -> Result<Vec<RGB>, DitherError> {
+    check(config, image)?;
+    let mut rgbs = dither_image(config, image, None, progress);
+    let reference = image_utils::luminance(&image_utils::dynimg_to_rgb(image));
+    draw_edges(config, &mut rgbs, &reference, image.width(), image.height());
+
+    Ok(rgbs)
+}
+
+// the dithering and its refinement, original is the size of the image before it was prepared,
+// to line up the importance map
+fn dither_image(
+    config: &ProcessConfig,
+    image: &DynamicImage,
+    original: Option<(u32, u32)>,
+    progress: impl FnMut(&DbsProgress),
+) -> Vec<RGB> {
+    let mut rgbs = image_utils::dynimg_to_rgb(image);
+    let reference = image_utils::luminance(&rgbs);
+
+    match &config.importance {
+        Some(importance) => importance.dither(
+            &mut rgbs,
+            &map_weights(config, &importance.image, original, image.width(), image.height()),
+            image.width(),
+            image.height(),
+            &config.dithering_type,
+            &config.color_map,
+            &config.dither_options(),
+        ),
+        None => config.dithering_type.dither(
+            &mut rgbs,
+            image.width(),
+            image.height(),
+            &config.color_map,
+            &config.dither_options(),
+        ),
+    }
+
+    if let Some(dbs) = &config.dbs {
+        dithering::dbs::refine(
+            &mut rgbs,
+            &reference,
+            image.width(),
+            image.height(),
+            &config.color_map,
+            dbs,
+            progress,
+        );
+    }
+
+    rgbs
+}
+
+// the masked region is prepared with its own tones, both sides are dithered and mixed by the
+// mask. there is no direct binary search nor importance map, the config rejects them
+fn dither_masked(
+    config: &ProcessConfig,
+    mask: &Mask,
+    prepared: &DynamicImage,
+    colored: &DynamicImage,
+    original: Option<(u32, u32)>,
+) -> Vec<RGB> {
+    let (width, height) = (prepared.width(), prepared.height());
+    let outside = image_utils::dynimg_to_rgb(prepared);
+    let inside = image_utils::dynimg_to_rgb(&adjust_tones(
+        config,
+        colored,
+        original,
+        mask.brigthness_delta,
+        mask.constrast_delta,
+    ));
+    let weights = map_weights(config, &mask.image, original, width, height);
+
+    dithering::mask::dither(
+        &Side {
+            data: &outside,
+            dithering_type: &config.dithering_type,
+            color_map: &config.color_map,
+        },
+        &Side {
+            data: &inside,
+            dithering_type: &mask.dithering_type,
+            color_map: &mask.color_map,
+        },
+        &weights,
+        width,
+        height,
+        mask.soft,
+        &config.dither_options(),
+    )
+}
+
+// grey levels of a mask or an importance map, from 0.0 to 1.0. the map is first stretched over
+// the original image, then goes through the same crop and resize, so both line up whatever the
+// crop, fit and aspect ratio of the map. without the original size, e.g. for an image prepared
+// elsewhere, the map is only stretched over the image
+fn map_weights(
+    config: &ProcessConfig,
+    map: &DynamicImage,
+    original: Option<(u32, u32)>,
+    width: u32,
+    height: u32,
+) -> Vec<f64> {
+    let mut resized = match original {
+        Some((original_width, original_height)) => {
+            let aligned = map.resize_exact(original_width, original_height, FilterType::Triangle);
+            let cropped = match &config.crop {
+                Some(crop) => resize::crop(&aligned, crop),
+                None => aligned,
+            };
+            resize::resize(
+                &cropped,
+                config.processing_width,
+                config.processing_height,
+                ResizeFilter::Triangle,
+                config.fit,
+            )
+        }
+        None => map.clone(),
+    };
+    if (resized.width(), resized.height()) != (width, height) {
+        resized = resized.resize_exact(width, height, FilterType::Triangle);
+    }
+
+    resized
+        .to_luma8()
+        .pixels()
+        .map(|pixel| pixel.0[0] as f64 / 255.0)
+        .collect()
+}
+
+fn draw_edges(config: &ProcessConfig, rgbs: &mut [RGB], reference: &[f64], width: u32, height: u32) {
+    if let Some(edges) = &config.edges {
+        let lines = edges::detect(reference, width, height, edges);
+        let color = edges.color.unwrap_or_else(|| darkest(&config.color_map));
+        edges::draw(rgbs, &lines, color, edges.only);
+    }
+}
+
+// turns the dithered pixels back into an image, on the padding canvas and scaled up for the output
+pub fn upscale(config: &ProcessConfig, rgbs: &[RGB], width: u32, height: u32) -> Result<DynamicImage, DitherError> {
+    config.validate()?;
+    let (rgbs, width, height) = match &config.pad {
+        Some(pad) => {
+            let color = pad.color.unwrap_or_else(|| lightest(&config.color_map));
+            (resize::pad(rgbs, width, height, pad, color)?, pad.width, pad.height)
+        }
+        None => (rgbs.to_vec(), width, height),
+    };
+    let new_image = image_utils::rgb_to_dynimg(&rgbs, width, height)?;
+    let (output_width, output_height) = config.output_scale.apply(width, height);
+    Ok(new_image.resize_exact(output_width, output_height, FilterType::Nearest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ditherer::Ditherer, edges::EdgeOptions, resize::Crop};
+    use image::{GrayImage, Luma};
+
+    #[test]
+    fn test_maps_line_up_with_the_crop() {
+        // a 40x20 image cropped to its right half, the mask is white from x = 15 on
+        let mut config = Ditherer::new().resize(10, 10).build().unwrap().config().clone();
+        config.crop = Some(Crop::Rect { x: 20, y: 0, width: 20, height: 20 });
+        let map = DynamicImage::ImageLuma8(GrayImage::from_fn(8, 2, |x, _| Luma([if x >= 3 { 255 } else { 0 }])));
+
+        let weights = map_weights(&config, &map, Some((40, 20)), 10, 10);
+        assert_eq!(weights.len(), 100);
+        assert!(weights.iter().all(|weight| *weight > 0.99));
+    }
+
+    #[test]
+    fn test_dither_draws_the_outlines() {
+        let red = RGB::from_u8(255, 0, 0, 255);
+        let mut config = Ditherer::new().resize(16, 16).build().unwrap().config().clone();
+        config.edges = Some(EdgeOptions {
+            color: Some(red),
+            ..Default::default()
+        });
+        let image = DynamicImage::ImageLuma8(GrayImage::from_fn(16, 16, |x, _| Luma([if x < 8 { 0 } else { 255 }])));
+
+        assert!(dither(&config, &image).unwrap().contains(&red));
+    }
 }
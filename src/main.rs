@@ -1,23 +1,231 @@
-use std::env;
+use std::{env, error::Error, fs};
 
-use ditherpunker::{config::ProcessConfig, image_utils, run};
-
-fn main() {
+use ditherpunker::{
+    color_palette::color_map_from_hex_list,
+    compare::{self, CompareOptions},
+    config::ProcessConfig,
+    dithering::{DitherOptions, DitheringType},
+    image_utils, process_with_progress, spectrum, stipple, upscale,
+    sweep::{self, parse_values},
+};
 
+fn main() -> Result<(), Box<dyn Error>> {
     let args: Vec<String> = env::args().collect();
 
-    let input_image_path = &args[1];
-    let output_image_path = &args[2];
-    let process_config_path = &args[3];
+    match args.get(1).map(|arg| arg.as_str()) {
+        Some("compare") => compare_command(&args[2..]),
+        Some("sweep") => sweep_command(&args[2..]),
+        Some("spectrum") => spectrum_command(&args[2..]),
+        _ => process_command(&args[1..]),
+    }
+}
+
+// ditherpunker <input> <output> <config> [--report] [--tile-preview <path>]
+// --tile-preview also writes the output repeated 2 x 2, to check the seams of tileable textures
+fn process_command(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let [input_image_path, output_image_path, process_config_path] = positional::<3>(args)?;
+
+    let image = image_utils::read_image(input_image_path)?;
+    let config: ProcessConfig = ProcessConfig::read_config(process_config_path)?;
+
+    // stippling has its own output, as an image or as svg circles
+    if config.stipple.is_some() {
+        let stippling = stipple(&config, &image)?;
+        if output_image_path.to_lowercase().ends_with(".svg") {
+            fs::write(output_image_path, stippling.to_svg(config.output_scale))?;
+            return Ok(());
+        }
+        image_utils::write_image(
+            &stippling.render(config.output_scale),
+            output_image_path,
+            image::ImageFormat::Png,
+        )?;
+        return Ok(());
+    }
 
-    let image = image_utils::read_image(input_image_path).unwrap();
-    let config: ProcessConfig = ProcessConfig::read_config(process_config_path).unwrap();
-    let processed_image = run(config, image).unwrap();
+    let dithered = process_with_progress(&config, &image, |progress| {
+        eprintln!(
+            "dbs pass {}/{}: {} changes, perceived error {:.6}",
+            progress.pass, progress.iterations, progress.changes, progress.error
+        )
+    })?;
+    if has_flag(args, "--report") {
+        println!("{}", dithered.report());
+    }
 
-    image_utils::write_image(
+    let processed_image = upscale(
+        &config,
+        &dithered.pixels,
+        dithered.prepared.width(),
+        dithered.prepared.height(),
+    )?;
+
+    if let Some(preview_path) = flag_values(args, "--tile-preview").last() {
+        image_utils::write_image(
+            &image_utils::tile(&processed_image, 2, 2),
+            preview_path,
+            image::ImageFormat::Png,
+        )?;
+    }
+
+    Ok(image_utils::write_image(
         &processed_image,
         output_image_path,
         image::ImageFormat::Png,
-    )
-    .unwrap();
+    )?)
+}
+
+// ditherpunker compare <input> <output> <config> [--dithering a,b] [--brightness x,y]
+//     [--contrast x,y] [--palette 000000,ffffff]... [--columns n]
+fn compare_command(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let [input_image_path, output_image_path, process_config_path] = positional::<3>(args)?;
+
+    let image = image_utils::read_image(input_image_path)?;
+    let config: ProcessConfig = ProcessConfig::read_config(process_config_path)?;
+
+    let options = compare_options(args)?;
+
+    let variants = compare::variants(&config, &options);
+    let columns = match flag_values(args, "--columns").last() {
+        Some(value) => value.parse()?,
+        None => (variants.len() as f64).sqrt().ceil() as u32,
+    };
+
+    let sheet = compare::contact_sheet(&image, &variants, columns)?;
+    Ok(image_utils::write_image(&sheet, output_image_path, image::ImageFormat::Png)?)
+}
+
+// ditherpunker sweep <input> <output_dir> <config> [--dithering a,b] [--brightness -20..20:10]
+//     [--contrast 0..60:10] [--palette 000000,ffffff]...
+fn sweep_command(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let [input_image_path, output_dir, process_config_path] = positional::<3>(args)?;
+
+    let image = image_utils::read_image(input_image_path)?;
+    let config: ProcessConfig = ProcessConfig::read_config(process_config_path)?;
+    let options = compare_options(args)?;
+
+    let entries = sweep::sweep(&image, &config, &options, output_dir)?;
+    println!("wrote {} images to {}", entries.len(), output_dir);
+    Ok(())
+}
+
+// ditherpunker spectrum <dithering_type|threshold_image> <output_prefix> [--grey 0.5] [--size 128] [--seed n]
+// analyzes the threshold map of the dithering type (or of the image), or with --grey a flat
+// patch of that grey once dithered, and writes <output_prefix>.csv and <output_prefix>.png
+fn spectrum_command(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let [source, output_prefix] = positional::<2>(args)?;
+    let size: u32 = match flag_values(args, "--size").last() {
+        Some(value) => value.parse()?,
+        None => 128,
+    };
+    let grey: Option<f64> = match flag_values(args, "--grey").last() {
+        Some(value) => Some(value.parse()?),
+        None => None,
+    };
+    let options = DitherOptions {
+        seed: match flag_values(args, "--seed").last() {
+            Some(value) => Some(value.parse()?),
+            None => None,
+        },
+        ..DitherOptions::default()
+    };
+
+    let (values, width, height, title) = match DitheringType::from_name(source) {
+        Some(dtype) => match (grey, dtype.threshold_map(size, size, &options)) {
+            (None, Some(map)) => (map, size, size, format!("{} threshold map", dtype.name())),
+            (grey, _) => {
+                let grey = grey.unwrap_or(0.5);
+                let patch = spectrum::flat_patch(&dtype, grey, size, size, &options);
+                (patch, size, size, format!("{} grey {}", dtype.name(), grey))
+            }
+        },
+        None => {
+            let image = image_utils::read_image(source)?;
+            let values = image_utils::luminance(&image_utils::dynimg_to_rgb(&image));
+            (values, image.width(), image.height(), source.clone())
+        }
+    };
+
+    let analysis = spectrum::analyze(&values, width, height);
+    spectrum::write_csv(&analysis, &format!("{}.csv", output_prefix))?;
+    Ok(image_utils::write_image(
+        &spectrum::plot(&analysis, &title),
+        &format!("{}.png", output_prefix),
+        image::ImageFormat::Png,
+    )?)
+}
+
+// the values shared by compare and sweep, brightness and contrast accept ranges like 0..60:10
+fn compare_options(args: &[String]) -> Result<CompareOptions, Box<dyn Error>> {
+    let mut options = CompareOptions::default();
+    for name in flag_list(args, "--dithering") {
+        match DitheringType::from_name(&name) {
+            Some(dtype) => options.dithering_types.push(dtype),
+            None => return Err(format!("Not recognized dithering_type {}", name).into()),
+        }
+    }
+    for value in flag_values(args, "--brightness") {
+        options
+            .brigthness_deltas
+            .extend(parse_values(&value)?.iter().map(|value| value.round() as i32));
+    }
+    for value in flag_values(args, "--contrast") {
+        options
+            .constrast_deltas
+            .extend(parse_values(&value)?.iter().map(|value| *value as f32));
+    }
+    for palette in flag_values(args, "--palette") {
+        options.color_maps.push(color_map_from_hex_list(&palette)?);
+    }
+    Ok(options)
+}
+
+// flags that don't take a value
+const SWITCHES: [&str; 1] = ["--report"];
+
+// the first N arguments that are neither flags nor flag values
+fn positional<const N: usize>(args: &[String]) -> Result<[&String; N], Box<dyn Error>> {
+    let mut found: Vec<&String> = Vec::new();
+    let mut index = 0;
+    while index < args.len() {
+        if SWITCHES.contains(&args[index].as_str()) {
+            index += 1;
+            continue;
+        }
+        if args[index].starts_with("--") {
+            index += 2;
+            continue;
+        }
+        found.push(&args[index]);
+        index += 1;
+    }
+
+    found
+        .into_iter()
+        .take(N)
+        .collect::<Vec<&String>>()
+        .try_into()
+        .map_err(|_| format!("expected {} positional arguments", N).into())
+}
+
+fn has_flag(args: &[String], flag: &str) -> bool {
+    args.iter().any(|arg| arg == flag)
+}
+
+// every value given to a flag, e.g. `--palette a --palette b` gives [a, b]
+fn flag_values(args: &[String], flag: &str) -> Vec<String> {
+    args.windows(2)
+        .filter(|pair| pair[0] == flag)
+        .map(|pair| pair[1].clone())
+        .collect()
+}
+
+// every comma separated value given to a flag, e.g. `--contrast 0,30` gives [0, 30]
+fn flag_list(args: &[String], flag: &str) -> Vec<String> {
+    flag_values(args, flag)
+        .iter()
+        .flat_map(|values| values.split(','))
+        .filter(|value| !value.is_empty())
+        .map(String::from)
+        .collect()
 }
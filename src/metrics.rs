@@ -0,0 +1,151 @@
+use std::fmt::Display;
+
+use crate::{
+    image_utils::{gaussian_blur, luminance},
+    pixel_util::RGB,
+};
+
+// all metrics compare luminance, the reference being the image right before dithering
+
+// standard deviation (in processing pixels) of the gaussian used as the model of the eye
+pub const DEFAULT_HVS_SIGMA: f64 = 1.5;
+
+const SSIM_SIGMA: f64 = 1.5;
+const SSIM_C1: f64 = 0.01 * 0.01;
+const SSIM_C2: f64 = 0.03 * 0.03;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Report {
+    pub mse: f64,
+    pub psnr: f64,
+    pub ssim: f64,
+    // the same as mse/psnr, but after both images went through a low-pass model of the eye
+    pub hvs_mse: f64,
+    pub hvs_psnr: f64,
+}
+
+pub fn report(reference: &[RGB], dithered: &[RGB], width: u32, height: u32) -> Report {
+    let reference = luminance(reference);
+    let dithered = luminance(dithered);
+
+    let mse = mse_values(&reference, &dithered);
+    let hvs_mse = hvs_mse_values(&reference, &dithered, width, height, DEFAULT_HVS_SIGMA);
+
+    Report {
+        mse,
+        psnr: psnr(mse),
+        ssim: ssim_values(&reference, &dithered, width, height),
+        hvs_mse,
+        hvs_psnr: psnr(hvs_mse),
+    }
+}
+
+pub fn mse(reference: &[RGB], dithered: &[RGB]) -> f64 {
+    mse_values(&luminance(reference), &luminance(dithered))
+}
+
+// peak signal to noise ratio in dB, values are in [0.0, 1.0] so the peak is 1.0
+pub fn psnr(mse: f64) -> f64 {
+    if mse == 0.0 {
+        return f64::INFINITY;
+    }
+    -10.0 * mse.log10()
+}
+
+// mean structural similarity, computed over gaussian windows (Wang et al. 2004)
+pub fn ssim(reference: &[RGB], dithered: &[RGB], width: u32, height: u32) -> f64 {
+    ssim_values(&luminance(reference), &luminance(dithered), width, height)
+}
+
+// error as seen from a distance: both images are blurred with a gaussian of the given
+// standard deviation, modelling the contrast sensitivity of the eye, before comparing them
+pub fn hvs_mse(reference: &[RGB], dithered: &[RGB], width: u32, height: u32, sigma: f64) -> f64 {
+    hvs_mse_values(&luminance(reference), &luminance(dithered), width, height, sigma)
+}
+
+fn mse_values(reference: &[f64], dithered: &[f64]) -> f64 {
+    if reference.is_empty() {
+        return 0.0;
+    }
+
+    reference
+        .iter()
+        .zip(dithered.iter())
+        .map(|(a, b)| (a - b).powi(2))
+        .sum::<f64>()
+        / reference.len() as f64
+}
+
+fn hvs_mse_values(reference: &[f64], dithered: &[f64], width: u32, height: u32, sigma: f64) -> f64 {
+    mse_values(
+        &gaussian_blur(reference, width, height, sigma),
+        &gaussian_blur(dithered, width, height, sigma),
+    )
+}
+
+fn ssim_values(reference: &[f64], dithered: &[f64], width: u32, height: u32) -> f64 {
+    if reference.is_empty() {
+        return 1.0;
+    }
+
+    let product = |a: &[f64], b: &[f64]| a.iter().zip(b.iter()).map(|(a, b)| a * b).collect::<Vec<f64>>();
+
+    let mu_x = gaussian_blur(reference, width, height, SSIM_SIGMA);
+    let mu_y = gaussian_blur(dithered, width, height, SSIM_SIGMA);
+    let xx = gaussian_blur(&product(reference, reference), width, height, SSIM_SIGMA);
+    let yy = gaussian_blur(&product(dithered, dithered), width, height, SSIM_SIGMA);
+    let xy = gaussian_blur(&product(reference, dithered), width, height, SSIM_SIGMA);
+
+    let mut sum = 0.0;
+    for i in 0..reference.len() {
+        let var_x = xx[i] - mu_x[i] * mu_x[i];
+        let var_y = yy[i] - mu_y[i] * mu_y[i];
+        let cov = xy[i] - mu_x[i] * mu_y[i];
+
+        sum += ((2.0 * mu_x[i] * mu_y[i] + SSIM_C1) * (2.0 * cov + SSIM_C2))
+            / ((mu_x[i] * mu_x[i] + mu_y[i] * mu_y[i] + SSIM_C1) * (var_x + var_y + SSIM_C2));
+    }
+
+    sum / reference.len() as f64
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "MSE:      {:.6}", self.mse)?;
+        writeln!(f, "PSNR:     {:.3} dB", self.psnr)?;
+        writeln!(f, "SSIM:     {:.6}", self.ssim)?;
+        writeln!(f, "HVS MSE:  {:.6}", self.hvs_mse)?;
+        write!(f, "HVS PSNR: {:.3} dB", self.hvs_psnr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gray(value: f64) -> RGB {
+        RGB { r: value, g: value, b: value, a: 1.0 }
+    }
+
+    #[test]
+    fn test_identical_images() {
+        let image = (0..64).map(|i| gray(i as f64 / 63.0)).collect::<Vec<RGB>>();
+        let report = report(&image, &image, 8, 8);
+
+        assert_eq!(report.mse, 0.0);
+        assert_eq!(report.psnr, f64::INFINITY);
+        assert!((report.ssim - 1.0).abs() < 1e-9);
+        assert_eq!(report.hvs_mse, 0.0);
+    }
+
+    #[test]
+    fn test_hvs_prefers_fine_patterns() {
+        // a checkerboard looks like flat 50% grey from a distance, two solid halves don't
+        let reference = vec![gray(0.5); 256];
+        let checkerboard = (0..256).map(|i| gray(((i % 16 + i / 16) % 2) as f64)).collect::<Vec<RGB>>();
+        let halves = (0..256).map(|i| gray((i % 16 / 8) as f64)).collect::<Vec<RGB>>();
+
+        assert_eq!(mse(&reference, &checkerboard), mse(&reference, &halves));
+        assert!(hvs_mse(&reference, &checkerboard, 16, 16, DEFAULT_HVS_SIGMA) < hvs_mse(&reference, &halves, 16, 16, DEFAULT_HVS_SIGMA));
+    }
+}
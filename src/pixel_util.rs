@@ -1,5 +1,7 @@
 use std::{fmt::{Debug, Display}, ops::{Add, Sub}};
 
+use crate::error::DitherError;
+
 // values are defined in a range [0.0, 1.0]
 #[derive(PartialEq, Debug, Clone, Copy)]
 pub struct RGB {
@@ -47,20 +49,20 @@ impl RGB {
         }
     }
 
-    pub fn from_hex(string: String) -> Result<RGB, Box<dyn std::error::Error>> {
-        let clean_string = String::from(string.trim().to_lowercase()).replace("#", "");
-        let r_str = &clean_string[0..2];
-        let g_str = &clean_string[2..4];
-        let b_str = &clean_string[4..6];
-
-        let r = u32::from_str_radix(r_str, 16)? as f64 / 255.0;
-        let g = u32::from_str_radix(g_str, 16)? as f64 / 255.0;
-        let b = u32::from_str_radix(b_str, 16)? as f64 / 255.0;
+    pub fn from_hex(string: String) -> Result<RGB, DitherError> {
+        let clean_string = string.trim().to_lowercase().replace("#", "");
+        let channel = |range: std::ops::Range<usize>| {
+            clean_string
+                .get(range)
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .map(|value| value as f64 / 255.0)
+                .ok_or_else(|| DitherError::Palette(format!("{} isn't a hex color", string)))
+        };
 
         Ok(RGB {
-            r: r,
-            g: g,
-            b: b,
+            r: channel(0..2)?,
+            g: channel(2..4)?,
+            b: channel(4..6)?,
             a: 1.0,
         })
     }
@@ -70,7 +72,7 @@ impl RGB {
         let g = (self.g * 255.0) as u8;
         let b = (self.b * 255.0) as u8;
         
-        format!("{:X}{:X}{:X}", r, g, b)
+        format!("{:02X}{:02X}{:02X}", r, g, b)
     }
 
     pub fn grayscale(&self) -> f64 {
@@ -88,9 +90,9 @@ impl RGB {
     }
 
     pub fn add_luminosity(&mut self, amount: f64) {
-        self.r = self.r + amount;
-        self.g = self.g + amount;
-        self.b = self.b + amount;
+        self.r += amount;
+        self.g += amount;
+        self.b += amount;
     }
 
     pub fn set_value(&mut self, value: f64) {
@@ -0,0 +1,314 @@
+use image::{imageops::FilterType, DynamicImage, Rgba, RgbaImage};
+
+use crate::{error::DitherError, pixel_util::RGB};
+
+// the filter used to bring the image to the processing size
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ResizeFilter {
+    Nearest,
+    Triangle,
+    CatmullRom,
+    #[default]
+    Gaussian,
+    Lanczos3,
+    // average of the pixels covered by each new pixel, best for large reductions
+    Box,
+}
+
+impl ResizeFilter {
+    pub const ALL: [ResizeFilter; 6] = [
+        Self::Nearest,
+        Self::Triangle,
+        Self::CatmullRom,
+        Self::Gaussian,
+        Self::Lanczos3,
+        Self::Box,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Nearest => "nearest",
+            Self::Triangle => "triangle",
+            Self::CatmullRom => "catmull_rom",
+            Self::Gaussian => "gaussian",
+            Self::Lanczos3 => "lanczos3",
+            Self::Box => "box",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ResizeFilter> {
+        Self::ALL.into_iter().find(|filter| filter.name() == name)
+    }
+}
+
+// how the image is brought to processing_width x processing_height
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum FitMode {
+    // inside the box, keeping the ratio
+    #[default]
+    Fit,
+    // covering the box, keeping the ratio, the overflow is cropped around the center
+    Fill,
+    // exactly the box, ignoring the ratio
+    Stretch,
+    // exactly processing_width wide, the height follows the ratio
+    Width,
+}
+
+impl FitMode {
+    pub const ALL: [FitMode; 4] = [Self::Fit, Self::Fill, Self::Stretch, Self::Width];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Fit => "fit",
+            Self::Fill => "fill",
+            Self::Stretch => "stretch",
+            Self::Width => "width",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<FitMode> {
+        Self::ALL.into_iter().find(|fit| fit.name() == name)
+    }
+}
+
+// output pixels per processing pixel, on each axis
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutputScale {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl OutputScale {
+    pub fn uniform(scale: f64) -> OutputScale {
+        OutputScale { x: scale, y: scale }
+    }
+
+    // size of the output for an image of the given size
+    pub fn apply(&self, width: u32, height: u32) -> (u32, u32) {
+        (
+            ((width as f64 * self.x).round() as u32).max(1),
+            ((height as f64 * self.y).round() as u32).max(1),
+        )
+    }
+}
+
+// part of the original image kept, before resizing
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Crop {
+    // in pixels of the original image
+    Rect { x: u32, y: u32, width: u32, height: u32 },
+    // in percents of the original size
+    Percent { x: f64, y: f64, width: f64, height: f64 },
+    // the largest area of this width / height ratio, centered on the focal point (from 0.0 to 1.0
+    // on each axis) as much as the borders allow
+    Aspect { ratio: f64, focus_x: f64, focus_y: f64 },
+}
+
+impl Crop {
+    // (x, y, width, height) of the kept area, always inside the image and at least a pixel
+    pub fn area(&self, width: u32, height: u32) -> (u32, u32, u32, u32) {
+        let (x, y, w, h) = match *self {
+            Crop::Rect { x, y, width, height } => (x, y, width, height),
+            Crop::Percent { x, y, width: w, height: h } => (
+                (x / 100.0 * width as f64).round() as u32,
+                (y / 100.0 * height as f64).round() as u32,
+                (w / 100.0 * width as f64).round() as u32,
+                (h / 100.0 * height as f64).round() as u32,
+            ),
+            Crop::Aspect { ratio, focus_x, focus_y } => {
+                let (w, h) = if width as f64 / height as f64 > ratio {
+                    ((height as f64 * ratio).round() as u32, height)
+                } else {
+                    (width, (width as f64 / ratio).round() as u32)
+                };
+                let (w, h) = (w.clamp(1, width), h.clamp(1, height));
+                let x = (focus_x * width as f64 - w as f64 / 2.0).round().clamp(0.0, (width - w) as f64);
+                let y = (focus_y * height as f64 - h as f64 / 2.0).round().clamp(0.0, (height - h) as f64);
+                (x as u32, y as u32, w, h)
+            }
+        };
+
+        let (x, y) = (x.min(width - 1), y.min(height - 1));
+        (x, y, w.clamp(1, width - x), h.clamp(1, height - y))
+    }
+}
+
+pub fn crop(image: &DynamicImage, crop: &Crop) -> DynamicImage {
+    let (x, y, width, height) = crop.area(image.width(), image.height());
+    image.crop_imm(x, y, width, height)
+}
+
+// exact canvas the dithered image is centered on, the overflow is cropped
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pad {
+    pub width: u32,
+    pub height: u32,
+    // the lightest color of the color map when not given
+    pub color: Option<RGB>,
+}
+
+pub fn pad(data: &[RGB], width: u32, height: u32, pad: &Pad, color: RGB) -> Result<Vec<RGB>, DitherError> {
+    if data.len() != width as usize * height as usize {
+        return Err(DitherError::dimensions(width, height, &format!("{} pixels don't fill the image", data.len())));
+    }
+    // position of the image on the canvas, negative when it overflows
+    let left = (pad.width as i64 - width as i64) / 2;
+    let top = (pad.height as i64 - height as i64) / 2;
+
+    Ok((0..pad.width as i64 * pad.height as i64)
+        .map(|index| {
+            let (x, y) = (index % pad.width as i64 - left, index / pad.width as i64 - top);
+            if x < 0 || y < 0 || x >= width as i64 || y >= height as i64 {
+                color
+            } else {
+                data[(y * width as i64 + x) as usize]
+            }
+        })
+        .collect())
+}
+
+// size of the resized image, before the crop of the fill mode
+pub fn target_size(width: u32, height: u32, box_width: u32, box_height: u32, fit: FitMode) -> (u32, u32) {
+    let ratio = |ratio: f64| {
+        (
+            ((width as f64 * ratio).round() as u32).max(1),
+            ((height as f64 * ratio).round() as u32).max(1),
+        )
+    };
+    let (wratio, hratio) = (box_width as f64 / width as f64, box_height as f64 / height as f64);
+
+    match fit {
+        FitMode::Fit => ratio(wratio.min(hratio)),
+        FitMode::Fill => ratio(wratio.max(hratio)),
+        FitMode::Stretch => (box_width.max(1), box_height.max(1)),
+        FitMode::Width => (box_width.max(1), ((height as f64 * wratio).round() as u32).max(1)),
+    }
+}
+
+pub fn resize(
+    image: &DynamicImage,
+    box_width: u32,
+    box_height: u32,
+    filter: ResizeFilter,
+    fit: FitMode,
+) -> DynamicImage {
+    let (width, height) = target_size(image.width(), image.height(), box_width, box_height, fit);
+    let resized = resize_exact(image, width, height, filter);
+
+    if fit == FitMode::Fill && (width > box_width || height > box_height) {
+        let (crop_width, crop_height) = (width.min(box_width), height.min(box_height));
+        resized.crop_imm((width - crop_width) / 2, (height - crop_height) / 2, crop_width, crop_height)
+    } else {
+        resized
+    }
+}
+
+fn resize_exact(image: &DynamicImage, width: u32, height: u32, filter: ResizeFilter) -> DynamicImage {
+    if (width, height) == (image.width(), image.height()) {
+        return image.clone();
+    }
+
+    let filter_type = match filter {
+        ResizeFilter::Nearest => FilterType::Nearest,
+        ResizeFilter::Triangle => FilterType::Triangle,
+        ResizeFilter::CatmullRom => FilterType::CatmullRom,
+        ResizeFilter::Gaussian => FilterType::Gaussian,
+        ResizeFilter::Lanczos3 => FilterType::Lanczos3,
+        // enlarging doesn't average anything
+        ResizeFilter::Box if width > image.width() || height > image.height() => FilterType::Nearest,
+        ResizeFilter::Box => return area_average(image, width, height),
+    };
+    image.resize_exact(width, height, filter_type)
+}
+
+// every new pixel is the average of the source area it covers, partly covered pixels weighted
+// by how much of them is covered
+fn area_average(image: &DynamicImage, width: u32, height: u32) -> DynamicImage {
+    let source = image.to_rgba8();
+    let (scale_x, scale_y) = (
+        source.width() as f64 / width as f64,
+        source.height() as f64 / height as f64,
+    );
+    // (first source pixel, weight) of the source pixels covered along an axis
+    let spans = |index: u32, scale: f64, size: u32| -> Vec<(u32, f64)> {
+        let (start, end) = (index as f64 * scale, (index + 1) as f64 * scale);
+        (start.floor() as u32..(end.ceil() as u32).min(size))
+            .map(|s| (s, (end.min(s as f64 + 1.0) - start.max(s as f64)).max(0.0)))
+            .collect()
+    };
+
+    RgbaImage::from_fn(width, height, |x, y| {
+        let mut sum = [0.0; 4];
+        let mut total = 0.0;
+        for (sy, wy) in spans(y, scale_y, source.height()) {
+            for (sx, wx) in spans(x, scale_x, source.width()) {
+                let pixel = source.get_pixel(sx, sy);
+                for (channel, value) in sum.iter_mut().zip(pixel.0) {
+                    *channel += value as f64 * wx * wy;
+                }
+                total += wx * wy;
+            }
+        }
+        Rgba(sum.map(|channel| (channel / total).round() as u8))
+    })
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_target_size() {
+        assert_eq!(target_size(400, 200, 100, 100, FitMode::Fit), (100, 50));
+        assert_eq!(target_size(400, 200, 100, 100, FitMode::Fill), (200, 100));
+        assert_eq!(target_size(400, 200, 100, 100, FitMode::Stretch), (100, 100));
+        assert_eq!(target_size(400, 200, 100, 10, FitMode::Width), (100, 50));
+
+        let image = DynamicImage::new_rgba8(400, 200);
+        let filled = resize(&image, 100, 100, ResizeFilter::Box, FitMode::Fill);
+        assert_eq!((filled.width(), filled.height()), (100, 100));
+    }
+
+    #[test]
+    fn test_crop() {
+        let rect = Crop::Rect { x: 150, y: 10, width: 100, height: 20 };
+        assert_eq!(rect.area(200, 100), (150, 10, 50, 20));
+        let percent = Crop::Percent { x: 25.0, y: 0.0, width: 50.0, height: 100.0 };
+        assert_eq!(percent.area(200, 100), (50, 0, 100, 100));
+
+        // square around a focal point, pushed back inside near the borders
+        let aspect = |focus_x| Crop::Aspect { ratio: 1.0, focus_x, focus_y: 0.5 };
+        assert_eq!(aspect(0.5).area(200, 100), (50, 0, 100, 100));
+        assert_eq!(aspect(0.6).area(200, 100), (70, 0, 100, 100));
+        assert_eq!(aspect(0.9).area(200, 100), (100, 0, 100, 100));
+    }
+
+    #[test]
+    fn test_pad() {
+        let (black, white) = (RGB::from_u8(0, 0, 0, 255), RGB::from_u8(255, 255, 255, 255));
+        let options = Pad { width: 4, height: 3, color: None };
+
+        let padded = pad(&[black; 2], 2, 1, &options, white).unwrap();
+        assert_eq!(padded.len(), 12);
+        assert_eq!(padded.iter().filter(|pixel| **pixel == black).count(), 2);
+        assert_eq!((padded[5], padded[6]), (black, black));
+
+        // too large, only the middle is kept
+        let cropped = pad(&[black, white, white, white, white, black], 6, 1, &Pad { width: 4, height: 1, color: None }, black);
+        assert_eq!(cropped.unwrap(), vec![white; 4]);
+        assert!(pad(&[black; 3], 2, 1, &options, white).is_err());
+    }
+
+    #[test]
+    fn test_area_average() {
+        // black and white columns average to grey
+        let image = RgbaImage::from_fn(6, 3, |x, _| if x % 2 == 0 { Rgba([0, 0, 0, 255]) } else { Rgba([255, 255, 255, 255]) });
+        let averaged = resize_exact(&image.into(), 2, 1, ResizeFilter::Box).to_rgba8();
+
+        // 3 source columns per pixel: two blacks and a white, then a white, a black and a white
+        assert_eq!(averaged.get_pixel(0, 0).0, [85, 85, 85, 255]);
+        assert_eq!(averaged.get_pixel(1, 0).0, [170, 170, 170, 255]);
+    }
+}
@@ -0,0 +1,374 @@
+use std::{f64::consts::PI, fs::File, io::Write};
+
+use image::{DynamicImage, Rgba, RgbaImage};
+
+use crate::{
+    color_palette::DEFAULT_COLOR_MAP,
+    dithering::{DitherOptions, DitheringType},
+    error::DitherError,
+    font,
+    pixel_util::RGB,
+};
+
+// radially averaged power spectrum and anisotropy of a 2D signal (Ulichney, "Digital Halftoning")
+#[derive(Debug, Clone)]
+pub struct Spectrum {
+    // radial frequency of each ring, in cycles per pixel
+    pub frequencies: Vec<f64>,
+    // mean power of each ring, normalized by the variance of the signal so white noise is 1.0
+    pub power: Vec<f64>,
+    // variance of the power in each ring over its squared mean, in dB
+    // a single periodogram of an isotropic signal sits around 0 dB, patterns go way above
+    pub anisotropy: Vec<f64>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Complex {
+    re: f64,
+    im: f64,
+}
+
+pub fn analyze(values: &[f64], width: u32, height: u32) -> Spectrum {
+    let (w, h) = (width as usize, height as usize);
+    let mean = values.iter().sum::<f64>() / values.len() as f64;
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64;
+
+    let mut data = values
+        .iter()
+        .map(|v| Complex { re: v - mean, im: 0.0 })
+        .collect::<Vec<Complex>>();
+
+    for y in 0..h {
+        let row = dft(&data[y * w..(y + 1) * w]);
+        data[y * w..(y + 1) * w].copy_from_slice(&row);
+    }
+    for x in 0..w {
+        let column = dft(&(0..h).map(|y| data[y * w + x]).collect::<Vec<Complex>>());
+        for (y, value) in column.into_iter().enumerate() {
+            data[y * w + x] = value;
+        }
+    }
+
+    // rings are one frequency step wide, up to the corners of the spectrum
+    let step = 1.0 / w.min(h) as f64;
+    let bins = (0.5f64.sqrt() / step).ceil() as usize + 1;
+    let mut sums = vec![0.0; bins];
+    let mut squares = vec![0.0; bins];
+    let mut counts = vec![0usize; bins];
+
+    for v in 0..h {
+        for u in 0..w {
+            if u == 0 && v == 0 {
+                continue;
+            }
+            let fu = signed_frequency(u, w);
+            let fv = signed_frequency(v, h);
+            let bin = ((fu * fu + fv * fv).sqrt() / step).round() as usize;
+
+            let value = data[v * w + u];
+            let power = if variance > 0.0 {
+                (value.re * value.re + value.im * value.im) / (values.len() as f64 * variance)
+            } else {
+                0.0
+            };
+
+            sums[bin] += power;
+            squares[bin] += power * power;
+            counts[bin] += 1;
+        }
+    }
+
+    let mut spectrum = Spectrum {
+        frequencies: Vec::new(),
+        power: Vec::new(),
+        anisotropy: Vec::new(),
+    };
+    for bin in 0..bins {
+        // a ring needs a few samples for the anisotropy to mean anything
+        if counts[bin] < 2 {
+            continue;
+        }
+        let mean_power = sums[bin] / counts[bin] as f64;
+        let power_variance = (squares[bin] / counts[bin] as f64 - mean_power * mean_power).max(0.0);
+
+        spectrum.frequencies.push(bin as f64 * step);
+        spectrum.power.push(mean_power);
+        spectrum.anisotropy.push(if mean_power > 0.0 && power_variance > 0.0 {
+            10.0 * (power_variance / (mean_power * mean_power)).log10()
+        } else {
+            // undefined for empty rings (and meaningless for perfectly flat ones)
+            f64::NAN
+        });
+    }
+
+    spectrum
+}
+
+// frequency in cycles per pixel of the index of a dft of the given size, in [-0.5, 0.5)
+fn signed_frequency(index: usize, size: usize) -> f64 {
+    if index <= size / 2 {
+        index as f64 / size as f64
+    } else {
+        index as f64 / size as f64 - 1.0
+    }
+}
+
+fn dft(input: &[Complex]) -> Vec<Complex> {
+    let n = input.len();
+    if n.is_power_of_two() {
+        let mut data = input.to_vec();
+        fft(&mut data);
+        return data;
+    }
+
+    (0..n)
+        .map(|k| {
+            let mut sum = Complex { re: 0.0, im: 0.0 };
+            for (t, value) in input.iter().enumerate() {
+                let angle = -2.0 * PI * ((k * t) % n) as f64 / n as f64;
+                sum.re += value.re * angle.cos() - value.im * angle.sin();
+                sum.im += value.re * angle.sin() + value.im * angle.cos();
+            }
+            sum
+        })
+        .collect()
+}
+
+// in place iterative radix-2 fft, the length must be a power of two
+fn fft(data: &mut [Complex]) {
+    let n = data.len();
+
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            data.swap(i, j);
+        }
+    }
+
+    let mut length = 2;
+    while length <= n {
+        let angle = -2.0 * PI / length as f64;
+        for start in (0..n).step_by(length) {
+            for k in 0..length / 2 {
+                let (sin, cos) = (angle * k as f64).sin_cos();
+                let a = data[start + k];
+                let b = data[start + k + length / 2];
+                let t = Complex {
+                    re: b.re * cos - b.im * sin,
+                    im: b.re * sin + b.im * cos,
+                };
+                data[start + k] = Complex { re: a.re + t.re, im: a.im + t.im };
+                data[start + k + length / 2] = Complex { re: a.re - t.re, im: a.im - t.im };
+            }
+        }
+        length <<= 1;
+    }
+}
+
+// luminance of a flat grey patch once dithered in black and white
+pub fn flat_patch(
+    dithering_type: &DitheringType,
+    grey: f64,
+    width: u32,
+    height: u32,
+    options: &DitherOptions,
+) -> Vec<f64> {
+    let mut data = vec![
+        RGB {
+            r: grey,
+            g: grey,
+            b: grey,
+            a: 1.0,
+        };
+        (width * height) as usize
+    ];
+    dithering_type.dither(&mut data, width, height, &DEFAULT_COLOR_MAP, options);
+    data.iter().map(|p| p.grayscale()).collect()
+}
+
+pub fn write_csv(spectrum: &Spectrum, path: &str) -> Result<(), DitherError> {
+    let mut file = File::create(path)?;
+    writeln!(file, "frequency,power,anisotropy_db")?;
+    for i in 0..spectrum.frequencies.len() {
+        writeln!(
+            file,
+            "{},{},{}",
+            spectrum.frequencies[i], spectrum.power[i], spectrum.anisotropy[i]
+        )?;
+    }
+    Ok(())
+}
+
+const PLOT_WIDTH: u32 = 640;
+const PANEL_HEIGHT: u32 = 220;
+const MARGIN: u32 = 48;
+const PLOT_BACKGROUND: Rgba<u8> = Rgba([255, 255, 255, 255]);
+const PLOT_FOREGROUND: Rgba<u8> = Rgba([0, 0, 0, 255]);
+const PLOT_REFERENCE: Rgba<u8> = Rgba([170, 170, 170, 255]);
+const PLOT_POWER: Rgba<u8> = Rgba([30, 60, 200, 255]);
+const PLOT_ANISOTROPY: Rgba<u8> = Rgba([200, 40, 40, 255]);
+
+// power on top, anisotropy at the bottom, the grey lines are what white noise would give
+pub fn plot(spectrum: &Spectrum, title: &str) -> DynamicImage {
+    let height = MARGIN + 2 * (PANEL_HEIGHT + MARGIN);
+    let mut image = RgbaImage::from_pixel(PLOT_WIDTH, height, PLOT_BACKGROUND);
+    font::draw_text(&mut image, MARGIN, MARGIN / 3, title, 2, PLOT_FOREGROUND);
+
+    let max_power = spectrum.power.iter().cloned().fold(2.0, f64::max);
+    draw_panel(
+        &mut image,
+        MARGIN,
+        "POWER",
+        &spectrum.frequencies,
+        &spectrum.power,
+        (0.0, max_power),
+        1.0,
+        PLOT_POWER,
+    );
+
+    let max_anisotropy = spectrum
+        .anisotropy
+        .iter()
+        .cloned()
+        .filter(|a| a.is_finite())
+        .fold(10.0, f64::max);
+    draw_panel(
+        &mut image,
+        2 * MARGIN + PANEL_HEIGHT,
+        "ANISOTROPY DB",
+        &spectrum.frequencies,
+        &spectrum.anisotropy,
+        (-10.0, max_anisotropy),
+        0.0,
+        PLOT_ANISOTROPY,
+    );
+
+    DynamicImage::ImageRgba8(image)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn draw_panel(
+    image: &mut RgbaImage,
+    top: u32,
+    name: &str,
+    xs: &[f64],
+    ys: &[f64],
+    (min_y, max_y): (f64, f64),
+    reference: f64,
+    color: Rgba<u8>,
+) {
+    let left = MARGIN as f64;
+    let right = (PLOT_WIDTH - MARGIN / 2) as f64;
+    let bottom = (top + PANEL_HEIGHT) as f64;
+    let max_x = 0.5f64.sqrt();
+
+    let to_x = |x: f64| left + (x / max_x) * (right - left);
+    let to_y = |y: f64| bottom - ((y.clamp(min_y, max_y) - min_y) / (max_y - min_y)) * PANEL_HEIGHT as f64;
+
+    draw_line(image, (left, to_y(reference)), (right, to_y(reference)), PLOT_REFERENCE);
+    draw_line(image, (left, top as f64), (left, bottom), PLOT_FOREGROUND);
+    draw_line(image, (left, bottom), (right, bottom), PLOT_FOREGROUND);
+
+    for tick in 0..=7 {
+        let x = to_x(tick as f64 / 10.0);
+        draw_line(image, (x, bottom), (x, bottom + 4.0), PLOT_FOREGROUND);
+        font::draw_text(image, x as u32 - 8, bottom as u32 + 8, &format!("{:.1}", tick as f64 / 10.0), 1, PLOT_FOREGROUND);
+    }
+    font::draw_text(image, 4, top, &format!("{:.1}", max_y), 1, PLOT_FOREGROUND);
+    font::draw_text(image, 4, bottom as u32 - 7, &format!("{:.1}", min_y), 1, PLOT_FOREGROUND);
+    font::draw_text(image, left as u32 + 8, top, name, 1, PLOT_FOREGROUND);
+
+    let points = xs
+        .iter()
+        .zip(ys.iter())
+        .filter(|(_, y)| y.is_finite())
+        .map(|(x, y)| (to_x(*x), to_y(*y)))
+        .collect::<Vec<(f64, f64)>>();
+    for pair in points.windows(2) {
+        draw_line(image, pair[0], pair[1], color);
+    }
+}
+
+fn draw_line(image: &mut RgbaImage, from: (f64, f64), to: (f64, f64), color: Rgba<u8>) {
+    let steps = (to.0 - from.0).abs().max((to.1 - from.1).abs()).ceil().max(1.0) as usize;
+    for step in 0..=steps {
+        let t = step as f64 / steps as f64;
+        let x = (from.0 + (to.0 - from.0) * t).round();
+        let y = (from.1 + (to.1 - from.1) * t).round();
+        if x >= 0.0 && y >= 0.0 && (x as u32) < image.width() && (y as u32) < image.height() {
+            image.put_pixel(x as u32, y as u32, color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // mean of the values for frequencies in [from, to)
+    fn band(spectrum: &Spectrum, values: &[f64], from: f64, to: f64) -> f64 {
+        let band = spectrum
+            .frequencies
+            .iter()
+            .zip(values.iter())
+            .filter(|(f, v)| **f >= from && **f < to && v.is_finite())
+            .map(|(_, v)| *v)
+            .collect::<Vec<f64>>();
+        band.iter().sum::<f64>() / band.len() as f64
+    }
+
+    #[test]
+    fn test_dft_matches_fft() {
+        let input = (0..8)
+            .map(|i| Complex { re: (i * i % 5) as f64, im: 0.0 })
+            .collect::<Vec<Complex>>();
+        let mut fast = input.clone();
+        fft(&mut fast);
+
+        // straight from the definition of the dft
+        let slow = (0..8)
+            .map(|k| {
+                input.iter().enumerate().fold(Complex { re: 0.0, im: 0.0 }, |acc, (t, v)| {
+                    let angle = -2.0 * PI * (k * t) as f64 / 8.0;
+                    Complex { re: acc.re + v.re * angle.cos(), im: acc.im + v.re * angle.sin() }
+                })
+            })
+            .collect::<Vec<Complex>>();
+
+        for (a, b) in fast.iter().zip(slow.iter()) {
+            assert!((a.re - b.re).abs() < 1e-9 && (a.im - b.im).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_blue_noise_is_blue() {
+        let map = DitheringType::BlueNoise.threshold_map(128, 128, &DitherOptions::default()).unwrap();
+        let spectrum = analyze(&map, 128, 128);
+
+        // little energy in the low frequencies, most of it in the high ones
+        assert!(band(&spectrum, &spectrum.power, 0.0, 0.1) < 0.2);
+        assert!(band(&spectrum, &spectrum.power, 0.3, 0.5) > 1.0);
+        // and no direction is preferred
+        assert!(band(&spectrum, &spectrum.anisotropy, 0.2, 0.5) < 1.0);
+
+        // which carries over to a dithered patch
+        let patch = flat_patch(&DitheringType::BlueNoise, 0.3, 128, 128, &DitherOptions::default());
+        let spectrum = analyze(&patch, 128, 128);
+        assert!(band(&spectrum, &spectrum.power, 0.0, 0.1) < 0.05);
+        assert!(band(&spectrum, &spectrum.anisotropy, 0.2, 0.5) < 1.0);
+    }
+
+    #[test]
+    fn test_bayer_is_anisotropic() {
+        let map = DitheringType::Bayer3.threshold_map(128, 128, &DitherOptions::default()).unwrap();
+        let spectrum = analyze(&map, 128, 128);
+
+        assert!(band(&spectrum, &spectrum.anisotropy, 0.2, 0.5) > 10.0);
+    }
+}
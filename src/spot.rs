@@ -0,0 +1,183 @@
+use crate::{
+    color_palette::ColorMapElement,
+    dithering::{DitherOptions, DitheringType},
+    pixel_util::RGB,
+};
+
+// a hue kept in color while the rest of the image is dithered with the color map
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpotColor {
+    // in degrees, 0 is red, 120 green and 240 blue
+    pub hue: f64,
+    // largest distance to the hue, in degrees
+    pub hue_tolerance: f64,
+    // smallest chroma (the difference between the largest and the smallest channel, from 0.0
+    // to 1.0), greys have no hue to match
+    pub min_chroma: f64,
+    // the accent, the hue at full saturation when not given
+    pub color: Option<RGB>,
+    // dithers between the accent and the background following the luminance, instead of a flat
+    // accent
+    pub dither: bool,
+}
+
+impl Default for SpotColor {
+    fn default() -> Self {
+        SpotColor {
+            hue: 0.0,
+            hue_tolerance: 20.0,
+            min_chroma: 0.2,
+            color: None,
+            dither: false,
+        }
+    }
+}
+
+impl SpotColor {
+    pub fn accent(&self) -> RGB {
+        self.color.unwrap_or_else(|| from_hue(self.hue))
+    }
+
+    fn matches(&self, pixel: &RGB) -> bool {
+        let (hue, chroma) = hue_and_chroma(pixel);
+        let distance = (hue - self.hue).rem_euclid(360.0);
+        chroma >= self.min_chroma && distance.min(360.0 - distance) <= self.hue_tolerance
+    }
+}
+
+// hue in degrees and chroma of a color
+pub fn hue_and_chroma(pixel: &RGB) -> (f64, f64) {
+    let max = pixel.r.max(pixel.g).max(pixel.b);
+    let min = pixel.r.min(pixel.g).min(pixel.b);
+    let chroma = max - min;
+    if chroma <= 0.0 {
+        return (0.0, 0.0);
+    }
+
+    let sector = if max == pixel.r {
+        ((pixel.g - pixel.b) / chroma).rem_euclid(6.0)
+    } else if max == pixel.g {
+        (pixel.b - pixel.r) / chroma + 2.0
+    } else {
+        (pixel.r - pixel.g) / chroma + 4.0
+    };
+    (sector * 60.0, chroma)
+}
+
+fn from_hue(hue: f64) -> RGB {
+    let sector = hue.rem_euclid(360.0) / 60.0;
+    let x = 1.0 - (sector % 2.0 - 1.0).abs();
+    let (r, g, b) = match sector as u32 {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x),
+    };
+    RGB { r, g, b, a: 1.0 }
+}
+
+// the index of the first spot color matching every pixel of the colored image
+pub fn find(spots: &[SpotColor], colored: &[RGB]) -> Vec<Option<usize>> {
+    colored
+        .iter()
+        .map(|pixel| spots.iter().position(|spot| spot.matches(pixel)))
+        .collect()
+}
+
+// puts the accents over the dithered pixels. dithered accents use the same dithering type as the
+// rest of the image, run on their own over the luminance placed between the accent and the
+// background
+#[allow(clippy::too_many_arguments)]
+pub fn apply(
+    data: &mut [RGB],
+    luminance: &[f64],
+    matched: &[Option<usize>],
+    spots: &[SpotColor],
+    width: u32,
+    height: u32,
+    background: RGB,
+    dithering_type: &DitheringType,
+    options: &DitherOptions,
+) {
+    for (index, spot) in spots.iter().enumerate() {
+        let accent = spot.accent();
+        let pixels = (0..data.len()).filter(|pixel| matched[*pixel] == Some(index));
+
+        if !spot.dither {
+            for pixel in pixels {
+                data[pixel] = accent;
+            }
+            continue;
+        }
+
+        // 0.0 at the luminance of the accent, 1.0 at the one of the background, everything
+        // else is background and doesn't matter
+        let range = background.grayscale() - accent.grayscale();
+        let mut values = vec![RGB { r: 1.0, g: 1.0, b: 1.0, a: 1.0 }; data.len()];
+        for pixel in pixels.clone() {
+            let value = if range.abs() > f64::EPSILON {
+                ((luminance[pixel] - accent.grayscale()) / range).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            values[pixel] = RGB { r: value, g: value, b: value, a: 1.0 };
+        }
+
+        let color_map = [
+            ColorMapElement { color: accent, scale: 1.0, offset: 0.0 },
+            ColorMapElement { color: background, scale: 1.0, offset: 0.0 },
+        ];
+        dithering_type.dither(&mut values, width, height, &color_map, options);
+        for pixel in pixels {
+            data[pixel] = values[pixel];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hue_and_chroma() {
+        let (hue, chroma) = hue_and_chroma(&RGB::from_u8(255, 0, 0, 255));
+        assert_eq!((hue, chroma), (0.0, 1.0));
+        let (hue, _) = hue_and_chroma(&RGB::from_u8(0, 0, 255, 255));
+        assert_eq!(hue, 240.0);
+        assert_eq!(hue_and_chroma(&RGB::from_u8(128, 128, 128, 255)).1, 0.0);
+
+        for hue in [0.0, 60.0, 150.0, 300.0] {
+            assert!((hue_and_chroma(&from_hue(hue)).0 - hue).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_spots_keep_their_accent() {
+        let red = SpotColor::default();
+        let colored = [
+            RGB::from_u8(250, 10, 20, 255),
+            // wraps around 360 degrees
+            RGB::from_u8(240, 10, 60, 255),
+            RGB::from_u8(20, 200, 40, 255),
+            RGB::from_u8(140, 120, 120, 255),
+        ];
+        let matched = find(&[red], &colored);
+        assert_eq!(matched, vec![Some(0), Some(0), None, None]);
+
+        let black = RGB::from_u8(0, 0, 0, 255);
+        let mut data = [black; 4];
+        let luminance = colored.iter().map(|pixel| pixel.grayscale()).collect::<Vec<f64>>();
+        let white = RGB::from_u8(255, 255, 255, 255);
+        apply(&mut data, &luminance, &matched, &[red], 4, 1, white, &DitheringType::Bayer0, &DitherOptions::default());
+        assert_eq!(data, [red.accent(), red.accent(), black, black]);
+
+        // dithered, the pixels are either the accent or the background
+        let dithered = SpotColor { dither: true, ..red };
+        let mut data = [black; 4];
+        apply(&mut data, &luminance, &matched, &[dithered], 4, 1, white, &DitheringType::FloydSteinberg, &DitherOptions::default());
+        assert!(data[..2].iter().all(|pixel| *pixel == red.accent() || *pixel == white));
+        assert_eq!(data[2..], [black, black]);
+    }
+}
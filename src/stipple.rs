@@ -0,0 +1,306 @@
+use image::{DynamicImage, Rgba, RgbaImage};
+use rand::Rng;
+
+use crate::{dithering::noise::SplitMix, pixel_util::RGB, resize::OutputScale};
+
+// samples wanted in the cell of every dot when computing centroids
+const CELL_SAMPLES: f64 = 64.0;
+
+// settings of the stippling
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StippleOptions {
+    // number of dots
+    pub points: usize,
+    // rounds of Lloyd relaxation, moving every dot to the centroid of its cell
+    pub iterations: u32,
+    // radius of the dots in the lightest and the darkest parts, in processing pixels
+    pub min_radius: f64,
+    pub max_radius: f64,
+}
+
+impl Default for StippleOptions {
+    fn default() -> Self {
+        StippleOptions {
+            points: 2000,
+            iterations: 20,
+            min_radius: 0.4,
+            max_radius: 1.2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stipple {
+    pub x: f64,
+    pub y: f64,
+    pub radius: f64,
+}
+
+// dots over a plain background, in processing pixels
+#[derive(Debug, Clone)]
+pub struct Stippling {
+    pub width: u32,
+    pub height: u32,
+    pub stipples: Vec<Stipple>,
+    pub color: RGB,
+    pub background: RGB,
+}
+
+impl Stippling {
+    // the dots drawn with hard edges, stretched like the rest of the output when the scale
+    // differs between x and y
+    pub fn render(&self, scale: OutputScale) -> DynamicImage {
+        let (width, height) = scale.apply(self.width, self.height);
+        let (sx, sy) = (width as f64 / self.width as f64, height as f64 / self.height as f64);
+        let color = to_rgba(self.color);
+        let mut image = RgbaImage::from_pixel(width, height, to_rgba(self.background));
+
+        for stipple in &self.stipples {
+            let (cx, cy) = (stipple.x * sx, stipple.y * sy);
+            let (rx, ry) = (stipple.radius * sx, stipple.radius * sy);
+            let min_x = (cx - rx).floor().max(0.0) as u32;
+            let min_y = (cy - ry).floor().max(0.0) as u32;
+            let max_x = ((cx + rx).ceil() as u32).min(image.width());
+            let max_y = ((cy + ry).ceil() as u32).min(image.height());
+
+            for y in min_y..max_y {
+                for x in min_x..max_x {
+                    let (dx, dy) = ((x as f64 + 0.5 - cx) / rx, (y as f64 + 0.5 - cy) / ry);
+                    if dx * dx + dy * dy <= 1.0 {
+                        image.put_pixel(x, y, color);
+                    }
+                }
+            }
+        }
+
+        DynamicImage::ImageRgba8(image)
+    }
+
+    // the dots as circles of an svg document, sized like the rendered image, the view box stays
+    // in processing pixels and is stretched when the scale differs between x and y
+    pub fn to_svg(&self, scale: OutputScale) -> String {
+        let (width, height) = scale.apply(self.width, self.height);
+
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\" preserveAspectRatio=\"none\">\n",
+            width, height, self.width, self.height
+        );
+        svg += &format!(
+            "<rect width=\"{}\" height=\"{}\" fill=\"#{}\"/>\n",
+            self.width,
+            self.height,
+            self.background.to_hex()
+        );
+        svg += &format!("<g fill=\"#{}\">\n", self.color.to_hex());
+        for stipple in &self.stipples {
+            svg += &format!(
+                "<circle cx=\"{:.3}\" cy=\"{:.3}\" r=\"{:.3}\"/>\n",
+                stipple.x, stipple.y, stipple.radius
+            );
+        }
+        svg += "</g>\n</svg>\n";
+        svg
+    }
+}
+
+fn to_rgba(color: RGB) -> Rgba<u8> {
+    let [r, g, b, a] = [color.r, color.g, color.b, color.a].map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+    Rgba([r, g, b, a])
+}
+
+// Secord's weighted Voronoi stippling: dots are scattered following the darkness of the image,
+// then every round each pixel goes to its nearest dot and the dot moves to the centroid of its
+// pixels weighted by their darkness, which spreads the dots evenly while keeping them where the
+// image is dark. dots are bigger in the darker parts
+pub fn stipple(
+    luminance: &[f64],
+    width: u32,
+    height: u32,
+    options: &StippleOptions,
+    seed: Option<u64>,
+) -> Vec<Stipple> {
+    let (w, h) = (width as usize, height as usize);
+    let density = luminance.iter().map(|l| 1.0 - l.clamp(0.0, 1.0)).collect::<Vec<f64>>();
+    if options.points == 0 || density.iter().all(|d| *d <= 0.0) {
+        return Vec::new();
+    }
+
+    // inverse transform sampling, a pixel is picked in proportion to its darkness out of the
+    // cumulative density, so even an almost white image takes one draw per dot
+    let cumulative = density
+        .iter()
+        .scan(0.0, |total, d| {
+            *total += d.max(0.0);
+            Some(*total)
+        })
+        .collect::<Vec<f64>>();
+    let total = cumulative[cumulative.len() - 1];
+    let mut rng = SplitMix::new(seed.unwrap_or_else(|| rand::rng().random()));
+    let mut points = (0..options.points)
+        .map(|_| {
+            let target = rng.next_unit() * total;
+            let index = cumulative.partition_point(|c| *c <= target).min(w * h - 1);
+            ((index % w) as f64 + rng.next_unit(), (index / w) as f64 + rng.next_unit())
+        })
+        .collect::<Vec<(f64, f64)>>();
+
+    // with few pixels per dot, pixels are split so that centroids don't snap to pixel centers
+    let pixels_per_point = (w * h) as f64 / options.points as f64;
+    let subsamples = (CELL_SAMPLES / pixels_per_point).sqrt().ceil().clamp(1.0, 8.0) as usize;
+
+    for _ in 0..options.iterations {
+        let grid = Grid::new(&points, w, h);
+        // weighted sums of x, y, and weights of every cell
+        let mut sums = vec![(0.0, 0.0, 0.0); points.len()];
+
+        for y in 0..h {
+            for x in 0..w {
+                let weight = density[y * w + x];
+                if weight <= 0.0 {
+                    continue;
+                }
+                for sub in 0..subsamples * subsamples {
+                    let px = x as f64 + (sub % subsamples) as f64 / subsamples as f64 + 0.5 / subsamples as f64;
+                    let py = y as f64 + (sub / subsamples) as f64 / subsamples as f64 + 0.5 / subsamples as f64;
+                    let nearest = grid.nearest(&points, px, py);
+                    sums[nearest].0 += px * weight;
+                    sums[nearest].1 += py * weight;
+                    sums[nearest].2 += weight;
+                }
+            }
+        }
+
+        for (point, (sx, sy, weight)) in points.iter_mut().zip(sums) {
+            // a dot without any dark pixel stays where it is
+            if weight > 0.0 {
+                *point = (sx / weight, sy / weight);
+            }
+        }
+    }
+
+    points
+        .into_iter()
+        .map(|(x, y)| {
+            let darkness = density[(y as usize).min(h - 1) * w + (x as usize).min(w - 1)];
+            Stipple {
+                x,
+                y,
+                radius: options.min_radius + (options.max_radius - options.min_radius) * darkness,
+            }
+        })
+        .collect()
+}
+
+// points bucketed in square cells, about one per cell, to find the nearest one quickly
+struct Grid {
+    cell: f64,
+    columns: usize,
+    rows: usize,
+    cells: Vec<Vec<usize>>,
+}
+
+impl Grid {
+    fn new(points: &[(f64, f64)], width: usize, height: usize) -> Grid {
+        let cell = ((width * height) as f64 / points.len() as f64).sqrt().max(1.0);
+        let columns = (width as f64 / cell).ceil() as usize;
+        let rows = (height as f64 / cell).ceil() as usize;
+
+        let mut cells = vec![Vec::new(); columns * rows];
+        for (index, (x, y)) in points.iter().enumerate() {
+            let column = ((x / cell) as usize).min(columns - 1);
+            let row = ((y / cell) as usize).min(rows - 1);
+            cells[row * columns + column].push(index);
+        }
+
+        Grid {
+            cell,
+            columns,
+            rows,
+            cells,
+        }
+    }
+
+    // looks through rings of cells around the position until no closer point can be found
+    fn nearest(&self, points: &[(f64, f64)], x: f64, y: f64) -> usize {
+        let column = ((x / self.cell) as isize).min(self.columns as isize - 1);
+        let row = ((y / self.cell) as isize).min(self.rows as isize - 1);
+        let (mut best, mut best_distance) = (0, f64::MAX);
+
+        for ring in 0..=self.columns.max(self.rows) as isize {
+            // anything in this ring is at least (ring - 1) cells away
+            let reach = (ring - 1).max(0) as f64 * self.cell;
+            if best_distance <= reach * reach {
+                break;
+            }
+
+            for r in row - ring..=row + ring {
+                for c in column - ring..=column + ring {
+                    let on_ring = (r - row).abs() == ring || (c - column).abs() == ring;
+                    if !on_ring || r < 0 || c < 0 || r >= self.rows as isize || c >= self.columns as isize {
+                        continue;
+                    }
+                    for index in &self.cells[r as usize * self.columns + c as usize] {
+                        let (px, py) = points[*index];
+                        let distance = (px - x) * (px - x) + (py - y) * (py - y);
+                        if distance < best_distance {
+                            best = *index;
+                            best_distance = distance;
+                        }
+                    }
+                }
+            }
+        }
+
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dots_follow_the_darkness() {
+        let (width, height) = (64, 32);
+        // dark on the left, white on the right
+        let luminance = (0..width * height)
+            .map(|index| (index % width) as f64 / (width - 1) as f64)
+            .collect::<Vec<f64>>();
+        let options = StippleOptions {
+            points: 300,
+            ..Default::default()
+        };
+
+        let stipples = stipple(&luminance, width as u32, height as u32, &options, Some(3));
+        assert_eq!(stipples.len(), 300);
+        let left = stipples.iter().filter(|s| s.x < width as f64 / 2.0).count();
+        assert!(left > 200, "{} dots on the dark half", left);
+        assert!(stipples.iter().all(|s| s.x >= 0.0 && s.x <= width as f64 && s.y >= 0.0 && s.y <= height as f64));
+
+        let svg = Stippling {
+            width: width as u32,
+            height: height as u32,
+            stipples,
+            color: RGB::from_u8(0, 0, 0, 255),
+            background: RGB::from_u8(255, 255, 255, 255),
+        }
+        .to_svg(OutputScale::uniform(2.0));
+        assert_eq!(svg.matches("<circle").count(), 300);
+    }
+
+    #[test]
+    fn test_almost_white_image_still_gets_its_dots() {
+        // a single barely grey pixel, rejection sampling would take ages to place them
+        let mut luminance = vec![1.0; 256 * 256];
+        luminance[1000] = 1.0 - 1e-9;
+        let options = StippleOptions {
+            points: 50,
+            iterations: 1,
+            ..Default::default()
+        };
+
+        let stipples = stipple(&luminance, 256, 256, &options, Some(3));
+        assert_eq!(stipples.len(), 50);
+        assert!(stipples.iter().all(|s| s.x.floor() == (1000 % 256) as f64 && s.y.floor() == (1000 / 256) as f64));
+    }
+}
@@ -0,0 +1,201 @@
+use std::{fs, io::Write, path::Path};
+
+use image::DynamicImage;
+use json::{object, JsonValue};
+
+use crate::{
+    compare::{self, CompareOptions},
+    config::ProcessConfig,
+    error::DitherError,
+    image_utils, metrics::Report, process, upscale,
+};
+
+// one rendered combination of the sweep
+#[derive(Debug, Clone)]
+pub struct SweepEntry {
+    pub image_file: String,
+    pub config_file: String,
+    pub config: ProcessConfig,
+    pub report: Report,
+}
+
+// renders every combination of the options into the output directory, together with the
+// exact config used for each image and a manifest (json and csv) linking them with their metrics
+pub fn sweep(
+    image: &DynamicImage,
+    base: &ProcessConfig,
+    options: &CompareOptions,
+    output_dir: &str,
+) -> Result<Vec<SweepEntry>, DitherError> {
+    if base.stipple.is_some() {
+        return Err(DitherError::config("stipple", "sweeps measure dithered images, not dots"));
+    }
+    fs::create_dir_all(output_dir)?;
+
+    let mut entries: Vec<SweepEntry> = Vec::new();
+    for (index, variant) in compare::variants(base, options).into_iter().enumerate() {
+        let config = variant.config;
+        let name = format!(
+            "{:03}_{}_b{}_c{}",
+            index,
+            config.dithering_type.name(),
+            config.brigthness_delta,
+            config.constrast_delta
+        );
+
+        let dithered = process(&config, image)?;
+        let report = dithered.report();
+        let output = upscale(
+            &config,
+            &dithered.pixels,
+            dithered.prepared.width(),
+            dithered.prepared.height(),
+        )?;
+
+        let image_file = format!("{}.png", name);
+        let config_file = format!("{}.json", name);
+        image_utils::write_image(
+            &output,
+            &path_in(output_dir, &image_file),
+            image::ImageFormat::Png,
+        )?;
+        config.write_config(path_in(output_dir, &config_file))?;
+
+        entries.push(SweepEntry {
+            image_file,
+            config_file,
+            config,
+            report,
+        });
+    }
+
+    write_manifest_json(&entries, &path_in(output_dir, "manifest.json"))?;
+    write_manifest_csv(&entries, &path_in(output_dir, "manifest.csv"))?;
+
+    Ok(entries)
+}
+
+fn path_in(dir: &str, file: &str) -> String {
+    Path::new(dir).join(file).to_string_lossy().to_string()
+}
+
+fn write_manifest_json(entries: &[SweepEntry], path: &str) -> Result<(), DitherError> {
+    let mut data = JsonValue::new_array();
+    for entry in entries {
+        data.push(object! {
+            image: entry.image_file.clone(),
+            config: entry.config_file.clone(),
+            dithering_type: entry.config.dithering_type.name(),
+            brigthness_delta: entry.config.brigthness_delta,
+            constrast_delta: entry.config.constrast_delta,
+            metrics: object! {
+                mse: entry.report.mse,
+                psnr: finite_or_null(entry.report.psnr),
+                ssim: entry.report.ssim,
+                hvs_mse: entry.report.hvs_mse,
+                hvs_psnr: finite_or_null(entry.report.hvs_psnr),
+            },
+        })?;
+    }
+
+    let mut file = fs::File::create(path)?;
+    file.write_all(data.pretty(2).as_bytes())?;
+    Ok(())
+}
+
+// infinity (a perfect psnr) is not valid json
+fn finite_or_null(value: f64) -> JsonValue {
+    if value.is_finite() {
+        value.into()
+    } else {
+        JsonValue::Null
+    }
+}
+
+fn write_manifest_csv(entries: &[SweepEntry], path: &str) -> Result<(), DitherError> {
+    let mut file = fs::File::create(path)?;
+    writeln!(
+        file,
+        "image,config,dithering_type,brigthness_delta,constrast_delta,mse,psnr,ssim,hvs_mse,hvs_psnr"
+    )?;
+    for entry in entries {
+        writeln!(
+            file,
+            "{},{},{},{},{},{},{},{},{},{}",
+            entry.image_file,
+            entry.config_file,
+            entry.config.dithering_type.name(),
+            entry.config.brigthness_delta,
+            entry.config.constrast_delta,
+            entry.report.mse,
+            entry.report.psnr,
+            entry.report.ssim,
+            entry.report.hvs_mse,
+            entry.report.hvs_psnr
+        )?;
+    }
+    Ok(())
+}
+
+// more values than this would be a sheet nobody can look at
+const MAX_VALUES: usize = 1024;
+
+// parses a comma separated list of values and inclusive ranges, e.g. "0..60:10" or "-20..20:5,35"
+// a range without a step goes up by 1
+pub fn parse_values(values: &str) -> Result<Vec<f64>, DitherError> {
+    let mut parsed: Vec<f64> = Vec::new();
+
+    for part in values.split(',').map(|part| part.trim()).filter(|part| !part.is_empty()) {
+        let number = |value: &str| {
+            value
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|value| value.is_finite())
+                .ok_or_else(|| DitherError::config(part, &format!("{} isn't a number", value)))
+        };
+        let Some((start, rest)) = part.split_once("..") else {
+            parsed.push(number(part)?);
+            continue;
+        };
+        let (end, step) = match rest.split_once(':') {
+            Some((end, step)) => (end, number(step)?),
+            None => (rest, 1.0),
+        };
+        let (start, end) = (number(start)?, number(end)?);
+        if step <= 0.0 {
+            return Err(DitherError::config(part, "the step should be positive"));
+        }
+        if (end - start) / step >= MAX_VALUES as f64 {
+            return Err(DitherError::config(part, &format!("more than {} values", MAX_VALUES)));
+        }
+
+        let mut index = 0;
+        // small tolerance so float steps still reach the end of the range
+        while start + step * index as f64 <= end + step * 1e-9 {
+            parsed.push(start + step * index as f64);
+            index += 1;
+        }
+        if parsed.len() > MAX_VALUES {
+            return Err(DitherError::config(part, &format!("more than {} values", MAX_VALUES)));
+        }
+    }
+
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_values() {
+        assert_eq!(parse_values("0..60:10").unwrap(), vec![0.0, 10.0, 20.0, 30.0, 40.0, 50.0, 60.0]);
+        assert_eq!(parse_values("-2..1").unwrap(), vec![-2.0, -1.0, 0.0, 1.0]);
+        assert_eq!(parse_values("5, 0..0.5:0.25").unwrap(), vec![5.0, 0.0, 0.25, 0.5]);
+        assert!(parse_values("0..10:0").is_err());
+        assert!(parse_values("0..inf").is_err());
+        assert!(parse_values("nan").is_err());
+        assert!(parse_values("0..1:1e-300").is_err());
+    }
+}
@@ -0,0 +1,203 @@
+// Golden regression suite: synthetic images go through every dithering type and the outputs
+// are compared against the hashes in tests/golden/hashes.txt, so any change to an algorithm
+// shows up as an explicit diff.
+//
+// After an intended change, regenerate the hashes with
+//     UPDATE_GOLDEN=1 cargo test --test golden
+// and review the images written to target/tmp/golden/ before committing.
+
+use std::{collections::BTreeMap, env, f64::consts::PI, fs, path::PathBuf};
+
+use ditherpunker::{
+    color_palette::{color_map_from_hex_list, ColorMapElement, DEFAULT_COLOR_MAP},
+    dithering::{
+        dot_diffusion::ClassMatrix, expression::ThresholdExpr, noise::NoiseDistribution,
+        DitherOptions, DitheringType,
+    },
+    image_utils,
+    pixel_util::RGB,
+};
+
+const SIZE: u32 = 64;
+
+fn golden_path() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden/hashes.txt")
+}
+
+fn output_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("golden")
+}
+
+// inputs are built from u8 values, like decoded images, so they don't depend on float rounding
+fn image(f: impl Fn(f64, f64) -> [u8; 3]) -> Vec<RGB> {
+    (0..SIZE * SIZE)
+        .map(|index| {
+            let x = (index % SIZE) as f64 / (SIZE - 1) as f64;
+            let y = (index / SIZE) as f64 / (SIZE - 1) as f64;
+            let [r, g, b] = f(x, y);
+            RGB::from_u8(r, g, b, 255)
+        })
+        .collect()
+}
+
+fn gray(value: f64) -> [u8; 3] {
+    let value = (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+    [value, value, value]
+}
+
+fn inputs() -> Vec<(&'static str, Vec<RGB>)> {
+    vec![
+        ("ramp", image(|x, _| gray(x))),
+        ("flat_25", image(|_, _| gray(0.25))),
+        ("flat_50", image(|_, _| gray(0.5))),
+        ("flat_75", image(|_, _| gray(0.75))),
+        (
+            "zone_plate",
+            image(|x, y| {
+                let (dx, dy) = (x - 0.5, y - 0.5);
+                gray(0.5 + 0.5 * (PI * 60.0 * (dx * dx + dy * dy)).cos())
+            }),
+        ),
+        (
+            "color_wheel",
+            image(|x, y| {
+                let hue = (y - 0.5).atan2(x - 0.5) / (2.0 * PI) + 0.5;
+                let channel = |offset: f64| {
+                    let distance = ((hue + offset).fract() * 6.0 - 3.0).abs();
+                    ((distance - 1.0).clamp(0.0, 1.0) * 255.0).round() as u8
+                };
+                [channel(0.0), channel(2.0 / 3.0), channel(1.0 / 3.0)]
+            }),
+        ),
+    ]
+}
+
+fn palettes() -> Vec<(&'static str, Vec<ColorMapElement>)> {
+    vec![
+        ("bw", DEFAULT_COLOR_MAP.to_vec()),
+        ("4c", color_map_from_hex_list("101010,0000aa,10f022,f0f0f0").unwrap()),
+    ]
+}
+
+// every dithering type with a fixed seed, plus the other flavours of random noise
+fn cases() -> Vec<(String, DitheringType, DitherOptions)> {
+    let seeded = DitherOptions {
+        seed: Some(1234),
+        ..DitherOptions::default()
+    };
+
+    let mut cases = DitheringType::ALL
+        .iter()
+        .map(|dtype| (dtype.name().to_string(), dtype.clone(), seeded))
+        .collect::<Vec<(String, DitheringType, DitherOptions)>>();
+    for hashed_noise in [false, true] {
+        for noise in NoiseDistribution::ALL {
+            if noise == NoiseDistribution::Uniform && !hashed_noise {
+                continue;
+            }
+            let name = format!("rand_{}{}", noise.name(), if hashed_noise { "_hashed" } else { "" });
+            let options = DitherOptions {
+                noise,
+                hashed_noise,
+                ..seeded
+            };
+            cases.push((name, DitheringType::Rand, options));
+        }
+    }
+    for (name, source) in [
+        ("expr_lines", "fract((x + y) / 6)"),
+        ("expr_radial", "fract(length(x - width / 2, y - height / 2) / 4) * 0.5 + luminance * 0.5"),
+    ] {
+        let expr = ThresholdExpr::parse(source).unwrap();
+        cases.push((name.to_string(), DitheringType::Expression(expr), seeded));
+    }
+    let spiral = ClassMatrix::new(&[
+        vec![0, 1, 2, 3],
+        vec![11, 12, 13, 4],
+        vec![10, 15, 14, 5],
+        vec![9, 8, 7, 6],
+    ])
+    .unwrap();
+    cases.push(("dot_diffusion_spiral".to_string(), DitheringType::CustomDotDiffusion(spiral), seeded));
+    // the matrices already fit in SIZE, only the types changed by wrapping
+    let tileable = DitherOptions { tileable: true, ..seeded };
+    for dtype in [
+        DitheringType::FloydSteinberg,
+        DitheringType::Ostromoukhov,
+        DitheringType::InterleavedGradientNoise,
+        DitheringType::R2,
+        DitheringType::DotDiffusion,
+    ] {
+        cases.push((format!("{}_tileable", dtype.name()), dtype, tileable));
+    }
+    cases
+}
+
+// FNV-1a, stable across platforms and Rust versions unlike the std hasher
+fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn read_golden() -> BTreeMap<String, String> {
+    fs::read_to_string(golden_path())
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| line.split_once(' '))
+        .map(|(name, hash)| (name.to_string(), hash.to_string()))
+        .collect()
+}
+
+#[test]
+fn golden_outputs() {
+    let mut actual: BTreeMap<String, String> = BTreeMap::new();
+    fs::create_dir_all(output_dir()).unwrap();
+
+    for (case_name, dithering_type, options) in cases() {
+        for (input_name, input) in inputs() {
+            for (palette_name, palette) in palettes() {
+                let mut data = input.clone();
+                dithering_type.dither(&mut data, SIZE, SIZE, &palette, &options);
+                let output = image_utils::rgb_to_dynimg(&data, SIZE, SIZE).unwrap();
+
+                let name = format!("{}/{}/{}", case_name, input_name, palette_name);
+                actual.insert(name.clone(), format!("{:016x}", hash(output.as_bytes())));
+
+                output
+                    .save(output_dir().join(format!("{}.png", name.replace('/', "_"))))
+                    .unwrap();
+            }
+        }
+    }
+
+    if env::var("UPDATE_GOLDEN").is_ok() {
+        let lines = actual
+            .iter()
+            .map(|(name, hash)| format!("{} {}\n", name, hash))
+            .collect::<String>();
+        fs::create_dir_all(golden_path().parent().unwrap()).unwrap();
+        fs::write(golden_path(), lines).unwrap();
+        return;
+    }
+
+    let golden = read_golden();
+    let mismatches = actual
+        .iter()
+        .filter(|(name, hash)| golden.get(*name) != Some(hash))
+        .map(|(name, _)| name.clone())
+        .collect::<Vec<String>>();
+    let stale = golden
+        .keys()
+        .filter(|name| !actual.contains_key(*name))
+        .cloned()
+        .collect::<Vec<String>>();
+
+    assert!(
+        mismatches.is_empty() && stale.is_empty(),
+        "golden outputs changed: {:?}, no longer produced: {:?} (outputs are in {})",
+        mismatches,
+        stale,
+        output_dir().display()
+    );
+}